@@ -1,28 +1,129 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::time::Instant;
+
+trait Precedence {
+    // Whether a rule puts the first page before the second
+    fn precedes(&self, before: u32, after: u32) -> bool;
+}
+
+// One row of bits per page, bit `after` of row `before` is set for every rule,
+// so precedence is a single lookup. Pages index the rows directly, which keeps
+// lookups free of hashing as long as page numbers stay small.
+struct Rules {
+    rows: Vec<Vec<u64>>,
+    len: usize,
+}
+
+impl Precedence for Rules {
+    fn precedes(&self, before: u32, after: u32) -> bool {
+        return self
+            .rows
+            .get(before as usize)
+            .and_then(|x| x.get(after as usize / 64))
+            .is_some_and(|x| x >> (after % 64) & 1 == 1);
+    }
+}
+
+// Pages that must come after each page, as rules were stored before the pair
+// index. Only kept to compare against in the bench.
+struct RuleLists(HashMap<u32, Vec<u32>>);
+
+impl RuleLists {
+    fn new(rules: &Rules) -> Self {
+        let mut lists: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (before, after) in rules.sorted_pairs() {
+            lists.entry(before).or_default().push(after);
+        }
+        return RuleLists(lists);
+    }
+}
+
+impl Precedence for RuleLists {
+    fn precedes(&self, before: u32, after: u32) -> bool {
+        return self.0.get(&before).is_some_and(|x| x.contains(&after));
+    }
+}
+
+impl Rules {
+    fn new() -> Self {
+        return Rules {
+            rows: vec![],
+            len: 0,
+        };
+    }
+
+    fn add(&mut self, before: u32, after: u32) {
+        if self.precedes(before, after) {
+            return;
+        }
+        let (before, after) = (before as usize, after as usize);
+        if self.rows.len() <= before {
+            self.rows.resize(before + 1, vec![]);
+        }
+        let row = &mut self.rows[before];
+        if row.len() <= after / 64 {
+            row.resize(after / 64 + 1, 0);
+        }
+        row[after / 64] |= 1 << (after % 64);
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        return self.len;
+    }
+
+    fn sorted_pairs(&self) -> Vec<(u32, u32)> {
+        let mut pairs = vec![];
+        for (before, row) in self.rows.iter().enumerate() {
+            for (word_idx, word) in row.iter().enumerate() {
+                for bit in (0..64).filter(|x| word >> x & 1 == 1) {
+                    pairs.push((before as u32, (word_idx * 64 + bit) as u32));
+                }
+            }
+        }
+        return pairs;
+    }
+}
 
 struct Inputs {
-    rules: HashMap<u32, Vec<u32>>,
+    rules: Rules,
     manuals: Vec<Vec<u32>>,
 }
 
 impl Inputs {
     pub fn new() -> Self {
         return Inputs {
-            rules: HashMap::new(),
+            rules: Rules::new(),
             manuals: Vec::new(),
         };
     }
 }
 
+#[derive(Debug)]
+struct Violation {
+    before: u32,
+    after: u32,
+    before_pos: usize,
+    after_pos: usize,
+}
+
+#[derive(Debug)]
+struct Move {
+    page: u32,
+    from: usize,
+    to: usize,
+}
+
 enum ParserState {
     Rules,
     Manuals,
 }
 
-fn read_rule(rules: &mut HashMap<u32, Vec<u32>>, line: String) {
+fn read_rule(rules: &mut Rules, line: String) {
     let parts: Vec<&str> = line.split("|").collect();
     let first = parts
         .get(0)
@@ -34,7 +135,7 @@ fn read_rule(rules: &mut HashMap<u32, Vec<u32>>, line: String) {
         .expect("Invalid rule")
         .parse()
         .expect("Not a number");
-    rules.entry(first).or_default().push(second);
+    rules.add(first, second);
 }
 
 fn read_manuals(manuals: &mut Vec<Vec<u32>>, line: String) {
@@ -62,19 +163,244 @@ fn read_inputs() -> Inputs {
     return inputs;
 }
 
-fn is_sorted(manual: &Vec<u32>, rules: &HashMap<u32, Vec<u32>>) -> bool {
+fn is_sorted<R: Precedence>(manual: &[u32], rules: &R) -> bool {
     for (idx, page) in manual.iter().enumerate() {
-        if let Some(after_pages) = rules.get(page) {
-            for prev_idx in 0..idx {
-                if after_pages.contains(&manual[prev_idx]) {
-                    return false;
-                }
+        for prev_idx in 0..idx {
+            if rules.precedes(*page, manual[prev_idx]) {
+                return false;
             }
         }
     }
     return true;
 }
 
+fn sort_manual<R: Precedence>(manual: &[u32], rules: &R) -> Vec<u32> {
+    let mut sorted_manual = manual.to_vec();
+    sorted_manual.sort_by(|x, y| {
+        if rules.precedes(*x, *y) {
+            return Ordering::Less;
+        } else if rules.precedes(*y, *x) {
+            return Ordering::Greater;
+        }
+        return Ordering::Equal;
+    });
+    return sorted_manual;
+}
+
+fn violations(manual: &[u32], rules: &Rules) -> Vec<Violation> {
+    let mut violations = vec![];
+    for (idx, page) in manual.iter().enumerate() {
+        for (prev_idx, prev_page) in manual.iter().enumerate().take(idx) {
+            if rules.precedes(*page, *prev_page) {
+                violations.push(Violation {
+                    before: *page,
+                    after: *prev_page,
+                    before_pos: idx,
+                    after_pos: prev_idx,
+                });
+            }
+        }
+    }
+    return violations;
+}
+
+// Every page outside the longest run already in sorted order has to move once,
+// so moving exactly those pages behind their sorted predecessor is minimal.
+fn fixing_moves(manual: &[u32], rules: &Rules) -> Vec<Move> {
+    let sorted_manual = sort_manual(manual, rules);
+    let ranks: Vec<usize> = manual
+        .iter()
+        .map(|page| {
+            sorted_manual
+                .iter()
+                .position(|x| x == page)
+                .expect("Page lost while sorting")
+        })
+        .collect();
+
+    // Longest increasing subsequence of ranks, O(n²) is plenty for a manual
+    let mut lengths = vec![1; ranks.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; ranks.len()];
+    for idx in 0..ranks.len() {
+        for prev_idx in 0..idx {
+            if ranks[prev_idx] < ranks[idx] && lengths[prev_idx] + 1 > lengths[idx] {
+                lengths[idx] = lengths[prev_idx] + 1;
+                previous[idx] = Some(prev_idx);
+            }
+        }
+    }
+    let mut fixed = HashSet::new();
+    let mut current = (0..ranks.len()).max_by_key(|idx| lengths[*idx]);
+    while let Some(idx) = current {
+        fixed.insert(manual[idx]);
+        current = previous[idx];
+    }
+
+    let mut moves = vec![];
+    let mut pages = manual.to_vec();
+    for (rank, page) in sorted_manual.iter().enumerate() {
+        if fixed.contains(page) {
+            continue;
+        }
+        let from = pages
+            .iter()
+            .position(|x| x == page)
+            .expect("Page lost while moving");
+        pages.remove(from);
+        let to = match rank {
+            0 => 0,
+            _ => {
+                pages
+                    .iter()
+                    .position(|x| *x == sorted_manual[rank - 1])
+                    .expect("Page lost while moving")
+                    + 1
+            }
+        };
+        pages.insert(to, *page);
+        fixed.insert(*page);
+        moves.push(Move {
+            page: *page,
+            from,
+            to,
+        });
+    }
+    return moves;
+}
+
+fn report(inputs: &Inputs) {
+    for (idx, manual) in inputs.manuals.iter().enumerate() {
+        let violations = violations(manual, &inputs.rules);
+        if violations.is_empty() {
+            continue;
+        }
+        let pages: Vec<String> = manual.iter().map(|x| x.to_string()).collect();
+        println!("Manual {}: {}", idx + 1, pages.join(","));
+        for violation in violations.iter() {
+            println!(
+                "  violates {}|{} ({} at position {}, {} at position {})",
+                violation.before,
+                violation.after,
+                violation.before,
+                violation.before_pos,
+                violation.after,
+                violation.after_pos
+            );
+        }
+        let moves = fixing_moves(manual, &inputs.rules);
+        println!("  fixed with {} move(s):", moves.len());
+        for page_move in moves.iter() {
+            println!(
+                "    move {} from position {} to {}",
+                page_move.page, page_move.from, page_move.to
+            );
+        }
+    }
+}
+
+// Prints the rules as a DOT digraph. With a manual only its pages are kept
+// and rules it breaks are drawn in red.
+fn graph(inputs: &Inputs, manual_idx: Option<usize>) {
+    let manual = manual_idx.map(|idx| {
+        idx.checked_sub(1)
+            .and_then(|idx| inputs.manuals.get(idx))
+            .expect("No such manual")
+    });
+    let violated: HashSet<(u32, u32)> = match manual {
+        Some(manual) => violations(manual, &inputs.rules)
+            .iter()
+            .map(|x| (x.before, x.after))
+            .collect(),
+        None => HashSet::new(),
+    };
+    println!("digraph rules {{");
+    if let Some(manual) = manual {
+        for page in manual.iter() {
+            println!("    {};", page);
+        }
+    }
+    for (before, after) in inputs.rules.sorted_pairs() {
+        if let Some(manual) = manual {
+            if !manual.contains(&before) || !manual.contains(&after) {
+                continue;
+            }
+        }
+        if violated.contains(&(before, after)) {
+            println!("    {} -> {} [color=red, penwidth=2];", before, after);
+        } else {
+            println!("    {} -> {};", before, after);
+        }
+    }
+    println!("}}");
+}
+
+// Small xorshift generator, good enough to shuffle benchmark manuals
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    return *state;
+}
+
+fn generate_inputs(pages: u32, manuals: usize, manual_len: usize) -> Inputs {
+    let mut inputs = Inputs::new();
+    let mut state = 0x2024_0005;
+    let mut order: Vec<u32> = (0..pages).collect();
+    for idx in (1..order.len()).rev() {
+        let other = (next_random(&mut state) % (idx as u64 + 1)) as usize;
+        order.swap(idx, other);
+    }
+    for (idx, before) in order.iter().enumerate() {
+        for after in order.iter().skip(idx + 1) {
+            inputs.rules.add(*before, *after);
+        }
+    }
+    for _ in 0..manuals {
+        let mut manual: Vec<u32> = vec![];
+        while manual.len() < manual_len.min(pages as usize) {
+            let page = (next_random(&mut state) % pages as u64) as u32;
+            if !manual.contains(&page) {
+                manual.push(page);
+            }
+        }
+        inputs.manuals.push(manual);
+    }
+    return inputs;
+}
+
+fn time_rules<R: Precedence>(name: &str, rules: &R, manuals: &[Vec<u32>]) {
+    let start = Instant::now();
+    let sorted_manuals: Vec<Vec<u32>> = manuals.iter().map(|x| sort_manual(x, rules)).collect();
+    println!("{}: sorted in {:?}", name, start.elapsed());
+    let start = Instant::now();
+    let valid = sorted_manuals
+        .iter()
+        .filter(|manual| is_sorted(manual, rules))
+        .count();
+    println!(
+        "{}: validated ({} valid) in {:?}",
+        name,
+        valid,
+        start.elapsed()
+    );
+    assert_eq!(valid, sorted_manuals.len());
+}
+
+// Validation is timed on sorted manuals, the only ones it has to check in full
+fn bench(pages: u32) {
+    let start = Instant::now();
+    let inputs = generate_inputs(pages, 100, 200);
+    println!(
+        "Generated {} rules for {} manuals in {:?}",
+        inputs.rules.len(),
+        inputs.manuals.len(),
+        start.elapsed()
+    );
+    let lists = RuleLists::new(&inputs.rules);
+    time_rules("Bit matrix", &inputs.rules, &inputs.manuals);
+    time_rules("Rule lists", &lists, &inputs.manuals);
+}
+
 fn part1(inputs: &Inputs) {
     let mut middle_sum = 0;
     for manual in inputs.manuals.iter() {
@@ -89,19 +415,7 @@ fn part2(inputs: &Inputs) {
     let mut middle_sum = 0;
     for manual in inputs.manuals.iter() {
         if !is_sorted(&manual, &inputs.rules) {
-            let mut sorted_manual = manual.to_vec();
-            sorted_manual.sort_by(|x, y| {
-                if let Some(pages) = inputs.rules.get(x) {
-                    if pages.contains(y) {
-                        return Ordering::Less;
-                    }
-                } else if let Some(pages) = inputs.rules.get(y) {
-                    if pages.contains(x) {
-                        return Ordering::Greater;
-                    }
-                }
-                return Ordering::Equal;
-            });
+            let sorted_manual = sort_manual(manual, &inputs.rules);
             middle_sum += sorted_manual[sorted_manual.len() / 2]
         }
     }
@@ -112,12 +426,33 @@ fn part2(inputs: &Inputs) {
 }
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|x| x.as_str()) == Some("bench") {
+        let pages = args
+            .get(2)
+            .map(|x| x.parse().expect("Invalid page count"))
+            .unwrap_or(2000);
+        bench(pages);
+        return;
+    }
     let inputs = read_inputs();
+    if args.get(1).map(|x| x.as_str()) == Some("graph") {
+        let manual_idx = args
+            .get(2)
+            .map(|x| x.parse().expect("Invalid manual number"));
+        graph(&inputs, manual_idx);
+        return;
+    }
     println!(
-        "Read {} rulesets for {} manuals",
+        "Read {} rules for {} manuals",
         inputs.rules.len(),
         inputs.manuals.len()
     );
-    part1(&inputs);
-    part2(&inputs);
+    match args.get(1).map(|x| x.as_str()) {
+        Some("report") => report(&inputs),
+        _ => {
+            part1(&inputs);
+            part2(&inputs);
+        }
+    }
 }
@@ -1,11 +1,19 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
+use std::thread;
+use std::time::Duration;
 
 #[derive(Eq, PartialEq, Clone)]
 enum MapElement {
     Obstacle,
     Empty,
-    Visited(Vec<Direction>),
+    Visited(DirectionSet),
+    // Can only be entered heading in its direction, blocks like an obstacle otherwise
+    OneWay(Direction, DirectionSet),
+    // Moves the guard onto the other teleporter with the same label
+    Teleporter(char, DirectionSet),
 }
 
 impl MapElement {
@@ -13,10 +21,81 @@ impl MapElement {
         match c {
             '#' => MapElement::Obstacle,
             '.' => MapElement::Empty,
-            '^' => MapElement::Visited(vec![Direction::Up]),
+            '^' | '>' | 'v' | '<' => MapElement::Visited(DirectionSet::single(
+                &Direction::from_arrow(c).expect("Not a guard"),
+            )),
+            'U' => MapElement::OneWay(Direction::Up, DirectionSet::default()),
+            'R' => MapElement::OneWay(Direction::Right, DirectionSet::default()),
+            'D' => MapElement::OneWay(Direction::Down, DirectionSet::default()),
+            'L' => MapElement::OneWay(Direction::Left, DirectionSet::default()),
+            '0'..='9' => MapElement::Teleporter(c, DirectionSet::default()),
             _ => panic!("Invalid map input"),
         }
     }
+
+    fn to_char(&self) -> char {
+        match self {
+            MapElement::Obstacle => '#',
+            MapElement::Empty => '.',
+            MapElement::Visited(dirs) => {
+                let vertical = dirs.contains(&Direction::Up) || dirs.contains(&Direction::Down);
+                let horizontal =
+                    dirs.contains(&Direction::Left) || dirs.contains(&Direction::Right);
+                match (vertical, horizontal) {
+                    (true, true) => '+',
+                    (false, true) => '-',
+                    (true, false) => '|',
+                    (false, false) => '.',
+                }
+            }
+            MapElement::OneWay(direction, _) => match direction {
+                Direction::Up => 'U',
+                Direction::Right => 'R',
+                Direction::Down => 'D',
+                Direction::Left => 'L',
+            },
+            MapElement::Teleporter(label, _) => *label,
+        }
+    }
+
+    // Fields the guard walks straight across without anything happening
+    fn is_open(&self) -> bool {
+        return matches!(self, MapElement::Empty | MapElement::Visited(_));
+    }
+
+    fn is_visited(&self) -> bool {
+        return match self {
+            MapElement::Visited(dirs)
+            | MapElement::OneWay(_, dirs)
+            | MapElement::Teleporter(_, dirs) => *dirs != DirectionSet::default(),
+            _ => false,
+        };
+    }
+
+    // Records the guard passing in `direction`, false if it already did so before
+    fn visit(&mut self, direction: &Direction) -> bool {
+        if *self == MapElement::Empty {
+            *self = MapElement::Visited(DirectionSet::default());
+        }
+        let dirs = match self {
+            MapElement::Visited(dirs)
+            | MapElement::OneWay(_, dirs)
+            | MapElement::Teleporter(_, dirs) => dirs,
+            _ => panic!("Guard cannot enter an obstacle"),
+        };
+        if dirs.contains(direction) {
+            return false;
+        }
+        dirs.insert(direction);
+        return true;
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+enum TurnPolicy {
+    Right,
+    Left,
+    Around,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
@@ -28,17 +107,71 @@ enum Direction {
 }
 
 impl Direction {
-    fn turn(&mut self) {
-        *self = match self {
-            Self::Up => Self::Right,
-            Self::Right => Self::Down,
-            Self::Down => Self::Left,
-            Self::Left => Self::Up,
+    fn from_arrow(c: char) -> Option<Direction> {
+        return match c {
+            '^' => Some(Self::Up),
+            '>' => Some(Self::Right),
+            'v' => Some(Self::Down),
+            '<' => Some(Self::Left),
+            _ => None,
+        };
+    }
+
+    fn turn(&mut self, policy: &TurnPolicy) {
+        let quarter_turns = match policy {
+            TurnPolicy::Right => 1,
+            TurnPolicy::Around => 2,
+            TurnPolicy::Left => 3,
+        };
+        for _ in 0..quarter_turns {
+            *self = match self {
+                Self::Up => Self::Right,
+                Self::Right => Self::Down,
+                Self::Down => Self::Left,
+                Self::Left => Self::Up,
+            };
+        }
+    }
+
+    fn arrow(&self) -> char {
+        return match self {
+            Self::Up => '^',
+            Self::Right => '>',
+            Self::Down => 'v',
+            Self::Left => '<',
         };
     }
+
+    fn reverse(&self) -> Direction {
+        return match self {
+            Self::Up => Self::Down,
+            Self::Right => Self::Left,
+            Self::Down => Self::Up,
+            Self::Left => Self::Right,
+        };
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+struct DirectionSet(u8);
+
+impl DirectionSet {
+    fn single(direction: &Direction) -> Self {
+        let mut set = DirectionSet::default();
+        set.insert(direction);
+        return set;
+    }
+
+    fn contains(&self, direction: &Direction) -> bool {
+        return self.0 & (1 << *direction as u8) != 0;
+    }
+
+    fn insert(&mut self, direction: &Direction) {
+        self.0 |= 1 << *direction as u8;
+    }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug)]
 struct Position {
     row: usize,
     col: usize,
@@ -62,71 +195,340 @@ impl Position {
         }
         return None;
     }
+
+    fn distance_ahead(&self, other: &Position, direction: &Direction) -> Option<usize> {
+        return match direction {
+            Direction::Up if other.col == self.col && other.row <= self.row => {
+                Some(self.row - other.row)
+            }
+            Direction::Down if other.col == self.col && other.row >= self.row => {
+                Some(other.row - self.row)
+            }
+            Direction::Left if other.row == self.row && other.col <= self.col => {
+                Some(self.col - other.col)
+            }
+            Direction::Right if other.row == self.row && other.col >= self.col => {
+                Some(other.col - self.col)
+            }
+            _ => None,
+        };
+    }
+}
+
+// For every field and direction the last field before the next obstacle or
+// special tile, or None if the guard would walk off the map. The table is as
+// wide as the widest row, the missing end of a shorter row counts as a stop
+// the guard walks off the map at.
+struct JumpTable {
+    cols: usize,
+    stops: Vec<[Option<Position>; 4]>,
+}
+
+impl JumpTable {
+    fn new(map: &Vec<Vec<MapElement>>) -> Self {
+        let rows = map.len();
+        let cols = map.iter().map(|x| x.len()).max().unwrap_or(0);
+        let mut stops = vec![[None; 4]; rows * cols];
+        for direction in [
+            Direction::Up,
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+        ] {
+            let lines: Vec<Vec<Position>> = match direction {
+                Direction::Up | Direction::Down => (0..cols)
+                    .map(|col| (0..rows).map(|row| Position { row, col }).collect())
+                    .collect(),
+                Direction::Left | Direction::Right => (0..rows)
+                    .map(|row| (0..cols).map(|col| Position { row, col }).collect())
+                    .collect(),
+            };
+            for mut line in lines {
+                if direction == Direction::Down || direction == Direction::Right {
+                    line.reverse();
+                }
+                let mut stop = None;
+                for pos in line {
+                    stops[pos.row * cols + pos.col][direction as usize] = stop;
+                    if !map[pos.row].get(pos.col).is_some_and(|x| x.is_open()) {
+                        stop = pos.step(&direction.reverse());
+                    }
+                }
+            }
+        }
+        return JumpTable { cols, stops };
+    }
+
+    fn index(&self, pos: &Position) -> usize {
+        return pos.row * self.cols + pos.col;
+    }
+
+    fn stop(&self, pos: &Position, direction: &Direction) -> Option<Position> {
+        return self.stops[self.index(pos)][*direction as usize];
+    }
 }
 
 #[derive(Clone)]
 struct Situation {
     map: Vec<Vec<MapElement>>,
+    start: (Position, Direction),
     position: Position,
     direction: Direction,
+    turn: TurnPolicy,
+    teleports: HashMap<Position, Position>,
+}
+
+struct Animation {
+    delay: Duration,
+    single_step: bool,
+}
+
+impl Animation {
+    fn new() -> Self {
+        return Animation {
+            delay: Duration::from_millis(50),
+            single_step: false,
+        };
+    }
+
+    fn draw(
+        &self,
+        map: &Vec<Vec<MapElement>>,
+        guard: (Position, Direction),
+        obstacle_options: &HashMap<Position, Cycle>,
+    ) {
+        print!("\x1b[2J\x1b[H{}", render(map, &[guard], obstacle_options));
+        if self.single_step {
+            print!("Press enter for the next step");
+            io::stdout().flush().expect("Cannot write to terminal");
+            let mut line = String::new();
+            io::stdin()
+                .read_line(&mut line)
+                .expect("Cannot read from terminal");
+        } else {
+            io::stdout().flush().expect("Cannot write to terminal");
+            thread::sleep(self.delay);
+        }
+    }
+}
+
+// The loop a guard ends up in, entered at `entry` while heading `direction`
+#[derive(PartialEq, Eq, Debug)]
+struct Cycle {
+    entry: Position,
+    direction: Direction,
+    length: usize,
+}
+
+// A field the guard entered for the first time, coming from `from`
+#[derive(PartialEq, Eq)]
+struct FirstVisit {
+    field: Position,
+    from: Position,
+    direction: Direction,
 }
 
 #[derive(PartialEq, Eq)]
 enum PathStatus {
-    Exited(usize),
+    Exited(Vec<FirstVisit>),
     Loops,
 }
 
-fn read_inputs() -> Situation {
+fn render(
+    map: &Vec<Vec<MapElement>>,
+    guards: &[(Position, Direction)],
+    obstacle_options: &HashMap<Position, Cycle>,
+) -> String {
+    let mut frame = String::new();
+    for (row, map_row) in map.iter().enumerate() {
+        for (col, field) in map_row.iter().enumerate() {
+            let pos = Position { row, col };
+            if let Some((_, direction)) = guards.iter().find(|(guard, _)| *guard == pos) {
+                frame.push(direction.arrow());
+            } else if obstacle_options.contains_key(&pos) {
+                frame.push('O');
+            } else {
+                frame.push(field.to_char());
+            }
+        }
+        frame.push('\n');
+    }
+    return frame;
+}
+
+// One situation per guard, each guard walks on its own copy of the map
+fn read_inputs(turn: TurnPolicy) -> Vec<Situation> {
     let file = File::open("./input").expect("Input file missing");
-    let mut map = Vec::new();
-    let mut guard = None;
+    let mut map: Vec<Vec<MapElement>> = Vec::new();
+    let mut guards = Vec::new();
+    let mut labels: HashMap<char, Vec<Position>> = HashMap::new();
     for (row, line) in io::BufReader::new(file).lines().flatten().enumerate() {
-        if let Some(col) = line.chars().position(|x| x == '^') {
-            guard = Some(Position { row, col })
+        for (col, c) in line.chars().enumerate() {
+            if let Some(direction) = Direction::from_arrow(c) {
+                guards.push((Position { row, col }, direction));
+            } else if c.is_ascii_digit() {
+                labels.entry(c).or_default().push(Position { row, col });
+            }
         }
         map.push(line.chars().map(MapElement::from_char).collect());
     }
-    return Situation {
-        map,
-        position: guard.expect("No guard found"),
-        direction: Direction::Up,
-    };
+    if guards.is_empty() {
+        panic!("No guard found");
+    }
+    let mut teleports = HashMap::new();
+    for (label, positions) in labels.iter() {
+        let [first, second] = positions[..] else {
+            panic!("Teleporter {} needs exactly one partner", label);
+        };
+        teleports.insert(first, second);
+        teleports.insert(second, first);
+    }
+    // Other guards' starting fields stay blocked for obstacles but count as unvisited
+    for (start, _) in guards.iter() {
+        map[start.row][start.col] = MapElement::Visited(DirectionSet::default());
+    }
+    return guards
+        .iter()
+        .map(|(position, direction)| {
+            let mut situation = Situation {
+                map: map.clone(),
+                start: (*position, *direction),
+                position: *position,
+                direction: *direction,
+                turn,
+                teleports: teleports.clone(),
+            };
+            situation.map[position.row][position.col].visit(direction);
+            situation
+        })
+        .collect();
 }
 
-fn follow_path(situation: &mut Situation, check_loops: bool) -> PathStatus {
-    let mut obstacle_options: Vec<Position> = vec![];
-    let initial_pos = situation.position;
+// Jumps from stop to stop with one extra obstacle placed on the map, a loop
+// shows up as reaching the same stop in the same direction twice.
+fn find_loop(
+    situation: &Situation,
+    jumps: &JumpTable,
+    mut position: Position,
+    mut direction: Direction,
+    obstacle: Position,
+) -> Option<Cycle> {
+    let mut seen = vec![DirectionSet::default(); jumps.stops.len()];
+    // Stops with the direction they were reached in, the steps taken so far
+    // and where the straight walk towards them began
+    let mut stops: Vec<(Position, Direction, usize, Position)> = vec![];
+    let mut steps = 0;
+    loop {
+        let mut stop = jumps.stop(&position, &direction);
+        if let Some(distance) = position.distance_ahead(&obstacle, &direction) {
+            match stop {
+                Some(field) if position.distance_ahead(&field, &direction) < Some(distance) => {}
+                _ => stop = obstacle.step(&direction.reverse()),
+            }
+        }
+        let stop = stop?;
+        let distance = position
+            .distance_ahead(&stop, &direction)
+            .expect("Stop not ahead of guard");
+        steps += distance;
+        let directions = &mut seen[jumps.index(&stop)];
+        if directions.contains(&direction) {
+            // The guard entered the loop on the way to its first repeated stop,
+            // either where it started walking or where the loop joins that way
+            let (_, _, first_steps, first_start) = stops
+                .iter()
+                .find(|(pos, dir, _, _)| *pos == stop && *dir == direction)
+                .expect("Stop not recorded");
+            let entry = match first_start.distance_ahead(&stop, &direction) {
+                Some(approach) if approach <= distance => *first_start,
+                _ => position,
+            };
+            return Some(Cycle {
+                entry,
+                direction,
+                length: steps - first_steps,
+            });
+        }
+        directions.insert(&direction);
+        stops.push((stop, direction, steps, position));
+
+        position = stop;
+        let ahead = stop.step(&direction).expect("Stop at the map border");
+        if ahead == obstacle {
+            direction.turn(&situation.turn);
+            continue;
+        }
+        let Some(field) = situation.map[ahead.row].get(ahead.col) else {
+            // Past the end of a short row
+            return None;
+        };
+        match *field {
+            MapElement::OneWay(allowed, _) if allowed == direction => {
+                position = ahead;
+                steps += 1;
+            }
+            MapElement::Teleporter(_, _) => {
+                position = situation.teleports[&ahead];
+                steps += 1;
+            }
+            _ => direction.turn(&situation.turn),
+        }
+    }
+}
+
+// When animating, every first visit is also checked for a loop, so candidate
+// obstacles show up as the guard passes them
+fn follow_path(situation: &mut Situation, animation: Option<&Animation>) -> PathStatus {
+    let mut path = vec![];
+    let mut turns_in_place = 0;
+    let jumps = animation.map(|_| JumpTable::new(&situation.map));
+    let mut candidates: HashMap<Position, Cycle> = HashMap::new();
     while let Some(new_pos) = situation.position.step(&situation.direction) {
         if let Some(map_row) = situation.map.get(new_pos.row) {
             if let Some(field) = map_row.get(new_pos.col) {
                 match field {
-                    MapElement::Empty => {
-                        if check_loops {
-                            let mut new_situation = situation.clone();
-                            new_situation.map[new_pos.row][new_pos.col] = MapElement::Obstacle;
-                            new_situation.direction.turn();
-                            if follow_path(&mut new_situation, false) == PathStatus::Loops {
-                                if !obstacle_options.contains(&new_pos) && new_pos != initial_pos {
-                                    obstacle_options.push(new_pos);
+                    MapElement::Obstacle => {
+                        turns_in_place += 1;
+                        situation.direction.turn(&situation.turn);
+                    }
+                    MapElement::OneWay(allowed, _) if *allowed != situation.direction => {
+                        turns_in_place += 1;
+                        situation.direction.turn(&situation.turn);
+                    }
+                    _ => {
+                        turns_in_place = 0;
+                        if *field == MapElement::Empty {
+                            let visit = FirstVisit {
+                                field: new_pos,
+                                from: situation.position,
+                                direction: situation.direction,
+                            };
+                            if let Some(jumps) = &jumps {
+                                if let Some(cycle) = candidate(situation, jumps, &visit) {
+                                    candidates.insert(new_pos, cycle);
                                 }
                             }
+                            path.push(visit);
                         }
-
-                        situation.map[new_pos.row][new_pos.col] =
-                            MapElement::Visited(vec![situation.direction]);
-                        situation.position = new_pos;
-                    }
-                    MapElement::Visited(dirs) => {
-                        if dirs.contains(&situation.direction) {
+                        if !situation.map[new_pos.row][new_pos.col].visit(&situation.direction) {
                             return PathStatus::Loops;
                         }
-                        let mut new_dirs = dirs.clone();
-                        new_dirs.push(situation.direction);
-                        situation.map[new_pos.row][new_pos.col] = MapElement::Visited(new_dirs);
                         situation.position = new_pos;
+                        if let Some(partner) = situation.teleports.get(&new_pos) {
+                            situation.position = *partner;
+                        }
                     }
-                    MapElement::Obstacle => situation.direction.turn(),
+                }
+                if turns_in_place == 4 {
+                    // Boxed in on all sides
+                    return PathStatus::Loops;
+                }
+                if let Some(animation) = animation {
+                    animation.draw(
+                        &situation.map,
+                        (situation.position, situation.direction),
+                        &candidates,
+                    );
                 }
             } else {
                 break;
@@ -135,30 +537,143 @@ fn follow_path(situation: &mut Situation, check_loops: bool) -> PathStatus {
             break;
         }
     }
-    return PathStatus::Exited(obstacle_options.len());
+    return PathStatus::Exited(path);
 }
 
-fn part1and2() {
-    let mut situation = read_inputs();
-    let result = follow_path(&mut situation, true);
-    let count: usize = situation
-        .map
-        .iter()
-        .map(|row| {
-            row.iter()
-                .filter(|x| match x {
-                    MapElement::Visited(_) => true,
-                    _ => false,
-                })
-                .count()
+// The loop an obstacle on a newly visited field sends the guard into, if any
+fn candidate(situation: &Situation, jumps: &JumpTable, visit: &FirstVisit) -> Option<Cycle> {
+    find_loop(situation, jumps, visit.from, visit.direction, visit.field)?;
+    // Walk again from the start, the route may join the loop before reaching
+    // this field
+    let (start, start_direction) = situation.start;
+    let cycle = find_loop(situation, jumps, start, start_direction, visit.field)
+        .expect("Loop vanished when walking from the start");
+    return Some(cycle);
+}
+
+// Only the first visit of a field can take an obstacle, otherwise the path
+// leading there would already change, so the walked path holds all candidates.
+fn obstacle_options(situation: &Situation, path: &Vec<FirstVisit>) -> HashMap<Position, Cycle> {
+    let jumps = JumpTable::new(&situation.map);
+    let mut options = HashMap::new();
+    for visit in path.iter() {
+        if let Some(cycle) = candidate(situation, &jumps, visit) {
+            options.insert(visit.field, cycle);
+        }
+    }
+    return options;
+}
+
+fn walk(situations: &mut Vec<Situation>, animation: Option<&Animation>) -> Vec<PathStatus> {
+    return situations
+        .iter_mut()
+        .enumerate()
+        .map(|(idx, situation)| {
+            let status = follow_path(situation, animation);
+            if status == PathStatus::Loops {
+                println!("Guard {} never leaves the map", idx + 1);
+            }
+            status
         })
-        .sum();
-    println!("Number of visited fields: {}", count);
-    if let PathStatus::Exited(options) = result {
-        println!("Number of obstacle options: {}", options);
+        .collect();
+}
+
+fn part1(situations: &Vec<Situation>) {
+    let mut visited: HashSet<Position> = HashSet::new();
+    for situation in situations.iter() {
+        for (row, map_row) in situation.map.iter().enumerate() {
+            for (col, field) in map_row.iter().enumerate() {
+                if field.is_visited() {
+                    let pos = Position { row, col };
+                    visited.insert(pos);
+                    // Teleporters only record entering, the guard also stood on the partner
+                    if let Some(partner) = situation.teleports.get(&pos) {
+                        visited.insert(*partner);
+                    }
+                }
+            }
+        }
     }
+    println!("Number of visited fields: {}", visited.len());
+}
+
+fn part2(situations: &Vec<Situation>, paths: &Vec<PathStatus>, show_options: bool) {
+    let mut all_options: HashMap<Position, Cycle> = HashMap::new();
+    for (situation, status) in situations.iter().zip(paths.iter()) {
+        let PathStatus::Exited(path) = status else {
+            continue;
+        };
+        let options = obstacle_options(situation, path);
+        if show_options {
+            print!("{}", render(&situation.map, &[situation.start], &options));
+            let mut positions: Vec<&Position> = options.keys().collect();
+            positions.sort();
+            for pos in positions {
+                let cycle = &options[pos];
+                println!(
+                    "Obstacle at ({}, {}) loops {} steps, entered at ({}, {}) heading {:?}",
+                    pos.row,
+                    pos.col,
+                    cycle.length,
+                    cycle.entry.row,
+                    cycle.entry.col,
+                    cycle.direction
+                );
+            }
+        }
+        for (pos, cycle) in options {
+            all_options.entry(pos).or_insert(cycle);
+        }
+    }
+    println!("Number of obstacle options: {}", all_options.len());
 }
 
 fn main() {
-    part1and2();
+    let mut animation: Option<Animation> = None;
+    let mut show_options = false;
+    let mut turn = TurnPolicy::Right;
+    let mut part = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--animate" => {
+                animation.get_or_insert_with(Animation::new);
+            }
+            "--delay" => {
+                let millis = args
+                    .next()
+                    .expect("Missing delay")
+                    .parse()
+                    .expect("Invalid delay");
+                animation.get_or_insert_with(Animation::new).delay = Duration::from_millis(millis);
+            }
+            "--step" => animation.get_or_insert_with(Animation::new).single_step = true,
+            "--options" => show_options = true,
+            "--part" => {
+                part = match args.next().expect("Missing part").as_str() {
+                    "1" => Some(1),
+                    "2" => Some(2),
+                    other => panic!("Unknown part {}", other),
+                }
+            }
+            "--turn" => {
+                turn = match args.next().expect("Missing turn policy").as_str() {
+                    "right" => TurnPolicy::Right,
+                    "left" => TurnPolicy::Left,
+                    "around" => TurnPolicy::Around,
+                    other => panic!("Unknown turn policy {}", other),
+                }
+            }
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+    // Both parts share the walk, part 2 only searches the walked path for loops
+    let mut situations = read_inputs(turn);
+    let paths = walk(&mut situations, animation.as_ref());
+    if part != Some(2) {
+        part1(&situations);
+    }
+    if part != Some(1) {
+        part2(&situations, &paths, show_options);
+    }
 }
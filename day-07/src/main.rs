@@ -1,12 +1,70 @@
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Display;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::ops::{Add, Div, Rem, Sub};
+use std::time::Instant;
 
+// Equations are stored wide, the solver narrows them to u64 when they fit
 #[derive(Debug)]
 struct Equation {
-    result: u64,
-    values: Vec<u64>,
+    result: u128,
+    values: Vec<u128>,
 }
 
+trait Number:
+    Copy
+    + Ord
+    + Display
+    + Into<u128>
+    + TryFrom<u128>
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const TWO: Self;
+    const TEN: Self;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn checked_pow(self, exponent: u32) -> Option<Self>;
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(impl Number for $t {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+            const TWO: Self = 2;
+            const TEN: Self = 10;
+
+            fn checked_add(self, other: Self) -> Option<Self> {
+                return <$t>::checked_add(self, other);
+            }
+
+            fn checked_sub(self, other: Self) -> Option<Self> {
+                return <$t>::checked_sub(self, other);
+            }
+
+            fn checked_mul(self, other: Self) -> Option<Self> {
+                return <$t>::checked_mul(self, other);
+            }
+
+            fn checked_pow(self, exponent: u32) -> Option<Self> {
+                return <$t>::checked_pow(self, exponent);
+            }
+        })*
+    };
+}
+
+impl_number!(u64, u128);
+
 fn read_inputs() -> Vec<Equation> {
     let file = File::open("./input").expect("Input file missing");
     let mut equations = vec![];
@@ -29,11 +87,174 @@ fn read_inputs() -> Vec<Equation> {
     return equations;
 }
 
-fn check_equation<'a, I>(total: u64, current: u64, mut iterator: I, combined_op: bool) -> bool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Mul,
+    Concat,
+    Sub,
+    Div,
+    Pow,
+}
+
+// What the left operand must have been for an operator to produce a result
+enum Undo<N> {
+    Exactly(N),
+    Anything,
+    Impossible,
+    Overflow,
+}
+
+// Why an operator cannot be applied to two values
+#[derive(Debug, PartialEq, Eq)]
+enum Failure {
+    Invalid,
+    Overflow,
+}
+
+enum Solution {
+    Found(Vec<Operator>),
+    NotFound,
+    // Only a wider number type can tell
+    Overflow,
+}
+
+impl Operator {
+    fn from_name(name: &str) -> Self {
+        return match name {
+            "add" => Self::Add,
+            "mul" => Self::Mul,
+            "concat" => Self::Concat,
+            "sub" => Self::Sub,
+            "div" => Self::Div,
+            "pow" => Self::Pow,
+            _ => panic!("Unknown operator {}", name),
+        };
+    }
+
+    fn symbol(&self) -> &'static str {
+        return match self {
+            Self::Add => "+",
+            Self::Mul => "*",
+            Self::Concat => "||",
+            Self::Sub => "-",
+            Self::Div => "/",
+            Self::Pow => "^",
+        };
+    }
+
+    // Results stay unsigned, so subtraction must not go negative and division
+    // must be exact
+    fn apply<N: Number>(&self, left: N, right: N) -> Result<N, Failure> {
+        return match self {
+            Self::Add => left.checked_add(right).ok_or(Failure::Overflow),
+            Self::Mul => left.checked_mul(right).ok_or(Failure::Overflow),
+            Self::Concat => shift(right)
+                .and_then(|x| left.checked_mul(x))
+                .and_then(|x| x.checked_add(right))
+                .ok_or(Failure::Overflow),
+            Self::Sub => left.checked_sub(right).ok_or(Failure::Invalid),
+            Self::Div if right != N::ZERO && left % right == N::ZERO => Ok(left / right),
+            Self::Div => Err(Failure::Invalid),
+            Self::Pow => match u32::try_from(right.into()) {
+                Ok(exponent) => left.checked_pow(exponent).ok_or(Failure::Overflow),
+                Err(_) if left <= N::ONE => Ok(left),
+                Err(_) => Err(Failure::Overflow),
+            },
+        };
+    }
+
+    fn undo<N: Number>(&self, result: N, right: N) -> Undo<N> {
+        let left = match self {
+            Self::Add => result.checked_sub(right),
+            Self::Mul if right == N::ZERO => {
+                return if result == N::ZERO {
+                    Undo::Anything
+                } else {
+                    Undo::Impossible
+                };
+            }
+            Self::Mul if result % right == N::ZERO => Some(result / right),
+            Self::Mul => None,
+            Self::Concat => match shift(right) {
+                Some(shift) if result % shift == right => Some(result / shift),
+                // No room left for digits in front of the right value
+                None if result == right => Some(N::ZERO),
+                _ => None,
+            },
+            Self::Sub => match result.checked_add(right) {
+                Some(left) => Some(left),
+                None => return Undo::Overflow,
+            },
+            Self::Div if right != N::ZERO => match result.checked_mul(right) {
+                Some(left) => Some(left),
+                None => return Undo::Overflow,
+            },
+            Self::Div => None,
+            Self::Pow if right == N::ZERO => {
+                return if result == N::ONE {
+                    Undo::Anything
+                } else {
+                    Undo::Impossible
+                };
+            }
+            Self::Pow => integer_root(result, right),
+        };
+        return match left {
+            Some(left) => Undo::Exactly(left),
+            None => Undo::Impossible,
+        };
+    }
+
+    // For positive values a running total never gets smaller
+    fn never_shrinks(&self) -> bool {
+        return match self {
+            Self::Add | Self::Mul | Self::Concat | Self::Pow => true,
+            Self::Sub | Self::Div => false,
+        };
+    }
+}
+
+// The power of ten a value is shifted by when concatenated to its right
+fn shift<N: Number>(right: N) -> Option<N> {
+    let mut shift = N::TEN;
+    while shift <= right {
+        shift = shift.checked_mul(N::TEN)?;
+    }
+    return Some(shift);
+}
+
+fn integer_root<N: Number>(value: N, exponent: N) -> Option<N> {
+    let Ok(exponent) = u32::try_from(exponent.into()) else {
+        // Only zero and one survive such a power without overflowing
+        return if value <= N::ONE { Some(value) } else { None };
+    };
+    let (mut low, mut high) = (N::ZERO, value);
+    while low <= high {
+        let mid = low + (high - low) / N::TWO;
+        match mid.checked_pow(exponent) {
+            Some(power) if power == value => return Some(mid),
+            Some(power) if power < value => low = mid + N::ONE,
+            _ => high = mid.checked_sub(N::ONE)?,
+        }
+    }
+    return None;
+}
+
+fn expression<N: Number>(values: &[N], operators: &[Operator]) -> String {
+    let mut expression = values[0].to_string();
+    for (operator, value) in operators.iter().zip(values.iter().skip(1)) {
+        expression += &format!(" {} {}", operator.symbol(), value);
+    }
+    return expression;
+}
+
+fn check_equation<'a, N, I>(total: N, current: N, mut iterator: I, operators: &[Operator]) -> bool
 where
-    I: Iterator<Item = &'a u64> + Clone,
+    N: Number + 'a,
+    I: Iterator<Item = &'a N> + Clone,
 {
-    if current > total {
+    if current > total && operators.iter().all(|x| x.never_shrinks()) {
         return false;
     }
     let Some(next) = iterator.next() else {
@@ -42,49 +263,352 @@ where
         }
         return false;
     };
-    if combined_op {
-        let combined = format!("{}{}", current, next)
-            .parse()
-            .expect("Number too large");
-        if check_equation(total, combined, iterator.clone(), combined_op) {
-            return true;
+    for operator in operators.iter() {
+        if let Ok(value) = operator.apply(current, *next) {
+            if check_equation(total, value, iterator.clone(), operators) {
+                return true;
+            }
         }
     }
-    if check_equation(total, current * next, iterator.clone(), combined_op) {
-        return true;
+    return false;
+}
+
+// Any valid way to combine the values, whatever the result
+fn any_expression<N: Number>(current: N, values: &[N], operators: &[Operator]) -> Solution {
+    let Some((next, rest)) = values.split_first() else {
+        return Solution::Found(vec![]);
+    };
+    let mut overflow = false;
+    for operator in operators.iter() {
+        let solution = match operator.apply(current, *next) {
+            Ok(value) => any_expression(value, rest, operators),
+            Err(Failure::Invalid) => Solution::NotFound,
+            Err(Failure::Overflow) => Solution::Overflow,
+        };
+        match solution {
+            Solution::Found(mut witness) => {
+                witness.insert(0, *operator);
+                return Solution::Found(witness);
+            }
+            Solution::Overflow => overflow = true,
+            Solution::NotFound => {}
+        }
     }
-    if check_equation(total, current + next, iterator, combined_op) {
-        return true;
+    return if overflow {
+        Solution::Overflow
+    } else {
+        Solution::NotFound
+    };
+}
+
+// Undoes the operations from the last value backwards, an operation only
+// applies if the remaining total can have been produced by it. Finds the
+// operators between the values.
+fn solve_backwards<N: Number>(total: N, values: &[N], operators: &[Operator]) -> Solution {
+    let Some((last, rest)) = values.split_last() else {
+        return Solution::NotFound;
+    };
+    if rest.is_empty() {
+        return if total == *last {
+            Solution::Found(vec![])
+        } else {
+            Solution::NotFound
+        };
     }
-    return false;
+    let mut overflow = false;
+    for operator in operators.iter() {
+        let solution = match operator.undo(total, *last) {
+            Undo::Exactly(left) => solve_backwards(left, rest, operators),
+            Undo::Anything => any_expression(rest[0], &rest[1..], operators),
+            Undo::Impossible => Solution::NotFound,
+            Undo::Overflow => Solution::Overflow,
+        };
+        match solution {
+            Solution::Found(mut witness) => {
+                witness.push(*operator);
+                return Solution::Found(witness);
+            }
+            Solution::Overflow => overflow = true,
+            Solution::NotFound => {}
+        }
+    }
+    return if overflow {
+        Solution::Overflow
+    } else {
+        Solution::NotFound
+    };
 }
 
-fn part1(equations: &Vec<Equation>) {
-    let result: u64 = equations
+// Solves in u64 and only goes to u128 if an intermediate value did not fit,
+// equations that overflow even u128 count as unsolved
+fn solve(equation: &Equation, operators: &[Operator]) -> Option<Vec<Operator>> {
+    let narrow: Option<Vec<u64>> = equation
+        .values
         .iter()
-        .filter(|e| {
-            let mut iter = e.values.iter();
-            let first = iter.next().expect("No values");
-            check_equation(e.result, *first, iter, false)
+        .map(|x| u64::try_from(*x).ok())
+        .collect();
+    if let (Ok(result), Some(values)) = (u64::try_from(equation.result), narrow) {
+        match solve_backwards(result, &values, operators) {
+            Solution::Found(witness) => return Some(witness),
+            Solution::NotFound => return None,
+            Solution::Overflow => {}
+        }
+    }
+    return match solve_backwards(equation.result, &equation.values, operators) {
+        Solution::Found(witness) => Some(witness),
+        Solution::NotFound => None,
+        Solution::Overflow => {
+            println!(
+                "Skipping equation for {}, its values do not fit u128",
+                equation.result
+            );
+            None
+        }
+    };
+}
+
+fn check_forwards(equation: &Equation, operators: &[Operator]) -> bool {
+    let mut iter = equation.values.iter();
+    let first = iter.next().expect("No values");
+    return check_equation(equation.result, *first, iter, operators);
+}
+
+// Number of operator assignments reaching each value after every prefix of
+// the values. Intermediate values that do not fit u128 are dropped along with
+// their assignments.
+fn prefix_counts(equation: &Equation, operators: &[Operator]) -> Vec<BTreeMap<u128, u128>> {
+    let prune = operators.iter().all(|x| x.never_shrinks()) && !equation.values.contains(&0);
+    let mut layers = vec![BTreeMap::from([(equation.values[0], 1)])];
+    for value in equation.values.iter().skip(1) {
+        let mut layer = BTreeMap::new();
+        for (current, count) in layers.last().expect("No values").iter() {
+            for operator in operators {
+                let Ok(next) = operator.apply(*current, *value) else {
+                    continue;
+                };
+                if prune && next > equation.result {
+                    continue;
+                }
+                let total: &mut u128 = layer.entry(next).or_insert(0);
+                *total = total
+                    .checked_add(*count)
+                    .expect("Too many assignments to count");
+            }
+        }
+        layers.push(layer);
+    }
+    return layers;
+}
+
+fn count_assignments(equation: &Equation, operators: &[Operator]) -> u128 {
+    let layers = prefix_counts(equation, operators);
+    return match layers.last() {
+        Some(layer) => layer.get(&equation.result).copied().unwrap_or(0),
+        None => 0,
+    };
+}
+
+// Enumerates assignments by walking back from the result, only through values
+// reachable from the front, so every branch taken ends in an assignment
+struct Assignments<'a> {
+    values: &'a [u128],
+    operators: &'a [Operator],
+    layers: Vec<BTreeMap<u128, u128>>,
+    // Value needed after a prefix and the operators chosen behind it, last first
+    pending: Vec<(usize, u128, Vec<Operator>)>,
+}
+
+impl<'a> Assignments<'a> {
+    fn new(equation: &'a Equation, operators: &'a [Operator]) -> Self {
+        let layers = prefix_counts(equation, operators);
+        let last = equation.values.len() - 1;
+        let mut pending = vec![];
+        if layers[last].contains_key(&equation.result) {
+            pending.push((last, equation.result, vec![]));
+        }
+        return Assignments {
+            values: &equation.values,
+            operators,
+            layers,
+            pending,
+        };
+    }
+}
+
+impl<'a> Iterator for Assignments<'a> {
+    type Item = Vec<Operator>;
+
+    fn next(&mut self) -> Option<Vec<Operator>> {
+        while let Some((idx, needed, chosen)) = self.pending.pop() {
+            if idx == 0 {
+                return Some(chosen.into_iter().rev().collect());
+            }
+            let right = self.values[idx];
+            let previous = &self.layers[idx - 1];
+            // Pushed in reverse so they come out in operator order
+            for operator in self.operators.iter().rev() {
+                let lefts: Vec<u128> = match operator.undo(needed, right) {
+                    Undo::Exactly(left) => vec![left],
+                    Undo::Anything => previous.keys().copied().collect(),
+                    Undo::Impossible | Undo::Overflow => vec![],
+                };
+                for left in lefts.into_iter().rev() {
+                    if previous.contains_key(&left) && operator.apply(left, right) == Ok(needed) {
+                        let mut chosen = chosen.clone();
+                        chosen.push(*operator);
+                        self.pending.push((idx - 1, left, chosen));
+                    }
+                }
+            }
+        }
+        return None;
+    }
+}
+
+fn count(equations: &Vec<Equation>, operators: &[Operator], show: bool) {
+    let mut total: u128 = 0;
+    let mut solvable = 0;
+    for equation in equations.iter() {
+        let assignments = count_assignments(equation, operators);
+        if assignments == 0 {
+            continue;
+        }
+        solvable += 1;
+        total = total
+            .checked_add(assignments)
+            .expect("Too many assignments to count");
+        println!("{}: {} assignment(s)", equation.result, assignments);
+        if show {
+            for assignment in Assignments::new(equation, operators) {
+                println!("  {}", expression(&equation.values, &assignment));
+            }
+        }
+    }
+    let symbols: Vec<&str> = operators.iter().map(|x| x.symbol()).collect();
+    println!(
+        "Total assignments ({}): {} over {} solvable equations",
+        symbols.join(" "),
+        total,
+        solvable
+    );
+}
+
+// Small xorshift generator for benchmark equations
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    return *state;
+}
+
+fn generate_equations(count: usize, len: usize) -> Vec<Equation> {
+    let mut state = 0x2024_0007;
+    let mut equations = vec![];
+    while equations.len() < count {
+        let values: Vec<u128> = (0..len)
+            .map(|_| (next_random(&mut state) % 99 + 1).into())
+            .collect();
+        let mut result = Some(values[0]);
+        for value in values.iter().skip(1) {
+            let operator = PART2_OPERATORS[(next_random(&mut state) % 3) as usize];
+            result = result.and_then(|x| operator.apply(x, *value).ok());
+        }
+        // Skip overflowing equations, every other one gets a result that is
+        // most likely unsolvable
+        let Some(mut result) = result.filter(|x| *x < u64::MAX.into()) else {
+            continue;
+        };
+        if equations.len() % 2 == 1 {
+            result += 1;
+        }
+        equations.push(Equation { result, values });
+    }
+    return equations;
+}
+
+fn bench(equations: &Vec<Equation>, name: &str) {
+    for operators in [PART1_OPERATORS, PART2_OPERATORS] {
+        let start = Instant::now();
+        let forwards = equations
+            .iter()
+            .filter(|e| check_forwards(e, operators))
+            .count();
+        let forwards_time = start.elapsed();
+        let start = Instant::now();
+        let backwards = equations
+            .iter()
+            .filter(|e| solve(e, operators).is_some())
+            .count();
+        let backwards_time = start.elapsed();
+        assert_eq!(forwards, backwards);
+        println!(
+            "{} ({} ops): {} solvable, forwards {:?}, backwards {:?}",
+            name,
+            operators.len(),
+            backwards,
+            forwards_time,
+            backwards_time
+        );
+    }
+}
+
+const PART1_OPERATORS: &[Operator] = &[Operator::Add, Operator::Mul];
+const PART2_OPERATORS: &[Operator] = &[Operator::Add, Operator::Mul, Operator::Concat];
+
+// Witnesses for all solvable equations, equations with a known witness are
+// not solved again
+fn solve_all(
+    equations: &Vec<Equation>,
+    operators: &[Operator],
+    known: &Vec<Option<Vec<Operator>>>,
+) -> Vec<Option<Vec<Operator>>> {
+    return equations
+        .par_iter()
+        .zip(known.par_iter())
+        .map(|(equation, known)| match known {
+            Some(witness) => Some(witness.clone()),
+            None => solve(equation, operators),
         })
-        .map(|e| e.result)
-        .sum();
+        .collect();
+}
+
+fn calibrate(
+    equations: &Vec<Equation>,
+    witnesses: &Vec<Option<Vec<Operator>>>,
+    show: bool,
+) -> u128 {
+    let mut result: u128 = 0;
+    for (equation, witness) in equations.iter().zip(witnesses.iter()) {
+        if let Some(witness) = witness {
+            if show {
+                println!(
+                    "{}: {}",
+                    equation.result,
+                    expression(&equation.values, witness)
+                );
+            }
+            result = result
+                .checked_add(equation.result)
+                .expect("Total does not fit u128");
+        }
+    }
+    return result;
+}
+
+fn part1(equations: &Vec<Equation>, show: bool) -> Vec<Option<Vec<Operator>>> {
+    let witnesses = solve_all(equations, PART1_OPERATORS, &vec![None; equations.len()]);
+    let result = calibrate(equations, &witnesses, show);
     println!(
         "Total test values of valid calibrations (2 ops): {}",
         result
     );
+    return witnesses;
 }
 
-fn part2(equations: &Vec<Equation>) {
-    let result: u64 = equations
-        .iter()
-        .filter(|e| {
-            let mut iter = e.values.iter();
-            let first = iter.next().expect("No values");
-            check_equation(e.result, *first, iter, true)
-        })
-        .map(|e| e.result)
-        .sum();
+// Part 2 only adds an operator, so everything solved in part 1 stays solved
+fn part2(equations: &Vec<Equation>, part1_witnesses: &Vec<Option<Vec<Operator>>>, show: bool) {
+    let witnesses = solve_all(equations, PART2_OPERATORS, part1_witnesses);
+    let result = calibrate(equations, &witnesses, show);
     println!(
         "Total test values of valid calibrations (3 ops): {}",
         result
@@ -94,6 +618,151 @@ fn part2(equations: &Vec<Equation>) {
 fn main() {
     let equations = read_inputs();
     println!("Found {} equations", equations.len());
-    part1(&equations);
-    part2(&equations);
+    let mut show = false;
+    let mut run_bench = false;
+    let mut run_count = false;
+    let mut operators: Option<Vec<Operator>> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "bench" => run_bench = true,
+            "count" => run_count = true,
+            "--show" => show = true,
+            "--threads" => {
+                let threads = args
+                    .next()
+                    .expect("Missing thread count")
+                    .parse()
+                    .expect("Invalid thread count");
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build_global()
+                    .expect("Cannot set up threads");
+            }
+            "--ops" => {
+                operators = Some(
+                    args.next()
+                        .expect("Missing operators")
+                        .split(",")
+                        .map(Operator::from_name)
+                        .collect(),
+                )
+            }
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+    if run_bench {
+        bench(&equations, "Input");
+        bench(&generate_equations(200, 12), "Generated");
+        return;
+    }
+    if run_count {
+        let operators = operators.unwrap_or(PART2_OPERATORS.to_vec());
+        count(&equations, &operators, show);
+        return;
+    }
+    if let Some(operators) = operators {
+        let witnesses = solve_all(&equations, &operators, &vec![None; equations.len()]);
+        let result = calibrate(&equations, &witnesses, show);
+        let symbols: Vec<&str> = operators.iter().map(|x| x.symbol()).collect();
+        println!(
+            "Total test values of valid calibrations ({}): {}",
+            symbols.join(" "),
+            result
+        );
+        return;
+    }
+    let witnesses = part1(&equations, show);
+    part2(&equations, &witnesses, show);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_at_u64_max() {
+        assert_eq!(Operator::Add.apply(u64::MAX, 1), Err(Failure::Overflow));
+        assert_eq!(Operator::Mul.apply(u64::MAX, 0), Ok(0));
+        assert_eq!(Operator::Pow.apply(u64::MAX, 0), Ok(1));
+        assert_eq!(Operator::Add.apply(u64::MAX as u128, 1), Ok(1 << 64));
+    }
+
+    #[test]
+    fn undoes_at_u64_max() {
+        assert!(matches!(Operator::Add.undo(u64::MAX, 1), Undo::Exactly(x) if x == u64::MAX - 1));
+        assert!(matches!(Operator::Mul.undo(0u64, 0), Undo::Anything));
+        assert!(matches!(Operator::Mul.undo(u64::MAX, 0), Undo::Impossible));
+        assert!(matches!(Operator::Pow.undo(1u64, 0), Undo::Anything));
+        assert!(matches!(Operator::Pow.undo(u64::MAX, 0), Undo::Impossible));
+    }
+
+    #[test]
+    fn concat_falls_back_to_u128() {
+        assert_eq!(Operator::Concat.apply(u64::MAX, 99), Err(Failure::Overflow));
+        assert_eq!(
+            Operator::Concat.apply(u64::MAX as u128, 99),
+            Ok(1844674407370955161599)
+        );
+        let equation = Equation {
+            result: 1844674407370955161599,
+            values: vec![u64::MAX as u128, 99],
+        };
+        assert_eq!(
+            solve(&equation, &[Operator::Add, Operator::Concat]),
+            Some(vec![Operator::Concat])
+        );
+    }
+
+    #[test]
+    fn sub_undo_falls_back_to_u128() {
+        assert!(matches!(Operator::Sub.undo(u64::MAX, 2), Undo::Overflow));
+        assert!(matches!(
+            Operator::Sub.undo(u64::MAX as u128, 2),
+            Undo::Exactly(x) if x == u64::MAX as u128 + 2
+        ));
+        // Only adding first works, and that goes past u64::MAX on the way
+        let equation = Equation {
+            result: u64::MAX as u128,
+            values: vec![u64::MAX as u128 - 1, 3, 2],
+        };
+        let narrow: Vec<u64> = vec![u64::MAX - 1, 3, 2];
+        let operators = [Operator::Add, Operator::Sub];
+        assert!(matches!(
+            solve_backwards(u64::MAX, &narrow, &operators),
+            Solution::Overflow
+        ));
+        assert_eq!(
+            solve(&equation, &operators),
+            Some(vec![Operator::Add, Operator::Sub])
+        );
+    }
+
+    #[test]
+    fn shifts_near_u64_max() {
+        assert_eq!(shift(u64::MAX), None);
+        assert_eq!(shift(10_000_000_000_000_000_000u64), None);
+        assert_eq!(
+            shift(9_999_999_999_999_999_999u64),
+            Some(10_000_000_000_000_000_000)
+        );
+        assert_eq!(shift(u64::MAX as u128), Some(100_000_000_000_000_000_000));
+        assert!(matches!(
+            Operator::Concat.undo(u64::MAX, u64::MAX),
+            Undo::Exactly(0)
+        ));
+    }
+
+    #[test]
+    fn gives_up_past_u128() {
+        let equation = Equation {
+            result: u128::MAX,
+            values: vec![1, 1],
+        };
+        assert!(matches!(
+            solve_backwards(u128::MAX, &[1, 1], &[Operator::Sub]),
+            Solution::Overflow
+        ));
+        assert_eq!(solve(&equation, &[Operator::Sub]), None);
+    }
 }
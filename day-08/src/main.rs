@@ -1,127 +1,444 @@
-use auto_ops::impl_op_ex;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::ops::{Add, Mul, Sub};
 
 type Map = Vec<Vec<char>>;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-struct Coordinate {
-    row: i32,
-    col: i32,
+// Position on a map of D dimensions, the last two are row and column
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+struct Vector<const D: usize>([i32; D]);
+
+impl<const D: usize> Vector<D> {
+    // Maps of fewer dimensions only use the trailing parts of a cell
+    fn from_cell(layer: usize, row: usize, col: usize) -> Self {
+        let cell: [i32; 3] = [layer, row, col].map(|x| x.try_into().expect("Map too large"));
+        return Vector(std::array::from_fn(|idx| cell[3 - D + idx]));
+    }
+
+    fn zip(&self, other: &Self, f: fn(i32, i32) -> i32) -> Self {
+        return Vector(std::array::from_fn(|idx| f(self.0[idx], other.0[idx])));
+    }
+
+    fn scale(&self, factor: i32) -> Self {
+        return Vector(self.0.map(|x| x * factor));
+    }
+
+    fn shrink(&self, divisor: i32) -> Self {
+        return Vector(self.0.map(|x| x / divisor));
+    }
+
+    fn to_json(self) -> String {
+        let parts: Vec<String> = self.0.iter().map(|x| x.to_string()).collect();
+        return format!("[{}]", parts.join(", "));
+    }
+}
+
+impl<const D: usize> Add for Vector<D> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        return self.zip(&other, |x, y| x + y);
+    }
 }
 
-impl Coordinate {
-    fn new(row: i32, col: i32) -> Self {
-        Coordinate { row, col }
+impl<const D: usize> Sub for Vector<D> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        return self.zip(&other, |x, y| x - y);
     }
 }
 
-impl_op_ex!(+ |a: &Coordinate, b: &Coordinate| -> Coordinate {
-    Coordinate::new(a.row + b.row, a.col + b.col)
-});
-impl_op_ex!(-|a: &Coordinate, b: &Coordinate| -> Coordinate {
-    Coordinate::new(a.row - b.row, a.col - b.col)
-});
-impl_op_ex!(*|a: &Coordinate, b: &i32| -> Coordinate { Coordinate::new(a.row * b, a.col * b) });
+impl<const D: usize> Mul<i32> for Vector<D> {
+    type Output = Self;
 
-struct AntinodeResult {
-    max_rows: i32,
-    max_cols: i32,
-    nodes: Vec<Coordinate>,
+    fn mul(self, factor: i32) -> Self {
+        return self.scale(factor);
+    }
 }
 
-impl AntinodeResult {
-    fn new(map: &Map) -> Self {
-        AntinodeResult {
-            max_rows: map.len().try_into().expect("Map to large"),
-            max_cols: map[0].len().try_into().expect("Map to large"),
-            nodes: vec![],
+// Every position on the map and the box around them. Rows may differ in
+// length, so the box alone does not tell whether a position is on the map.
+struct Area<const D: usize> {
+    cells: HashSet<Vector<D>>,
+    low: [i32; D],
+    high: [i32; D],
+}
+
+impl<const D: usize> Area<D> {
+    fn new(layers: &[Map]) -> Self {
+        let mut cells = HashSet::new();
+        for (layer, map) in layers.iter().enumerate() {
+            for (row, row_values) in map.iter().enumerate() {
+                for col in 0..row_values.len() {
+                    cells.insert(Vector::from_cell(layer, row, col));
+                }
+            }
         }
+        let mut low = [i32::MAX; D];
+        let mut high = [i32::MIN; D];
+        for cell in cells.iter() {
+            for idx in 0..D {
+                low[idx] = low[idx].min(cell.0[idx]);
+                high[idx] = high[idx].max(cell.0[idx]);
+            }
+        }
+        return Area { cells, low, high };
+    }
+
+    // Inside the box around the map, nothing further out can be valid
+    fn in_bounds(&self, node: &Vector<D>) -> bool {
+        return (0..D).all(|idx| self.low[idx] <= node.0[idx] && node.0[idx] <= self.high[idx]);
+    }
+
+    fn valid(&self, node: &Vector<D>) -> bool {
+        return self.cells.contains(node);
     }
+}
+
+struct AntinodeResult<'a, const D: usize> {
+    area: &'a Area<D>,
+    nodes: HashSet<Vector<D>>,
+}
 
-    fn valid(&self, node: &Coordinate) -> bool {
-        if 0 <= node.row && node.row < self.max_rows && 0 <= node.col && node.col < self.max_cols {
-            return true;
+impl<'a, const D: usize> AntinodeResult<'a, D> {
+    fn new(area: &'a Area<D>) -> Self {
+        AntinodeResult {
+            area,
+            nodes: HashSet::new(),
         }
-        return false;
     }
 
-    fn push(&mut self, node: Coordinate) {
-        if self.valid(&node) && !self.nodes.contains(&node) {
-            self.nodes.push(node);
+    fn push(&mut self, node: Vector<D>) {
+        if self.area.valid(&node) {
+            self.nodes.insert(node);
         }
     }
 }
 
-fn read_inputs() -> Map {
+// Maps separated by empty lines are stacked as layers of a volume
+fn read_inputs() -> Vec<Map> {
     let file = File::open("./input").expect("Input file missing");
-    return io::BufReader::new(file)
-        .lines()
-        .flatten()
-        .map(|line| line.chars().collect())
-        .collect();
+    let mut layers = vec![vec![]];
+    for line in io::BufReader::new(file).lines().flatten() {
+        if line.is_empty() {
+            layers.push(vec![]);
+            continue;
+        }
+        layers
+            .last_mut()
+            .expect("No layers")
+            .push(line.chars().collect());
+    }
+    layers.retain(|x| !x.is_empty());
+    return layers;
 }
 
-fn map_to_coordinates(map: &Map) -> HashMap<char, Vec<Coordinate>> {
-    let mut coords = HashMap::<char, Vec<Coordinate>>::new();
-    for (row, row_values) in map.iter().enumerate() {
-        for (col, value) in row_values.iter().enumerate() {
-            if *value == '.' {
-                continue;
+fn map_to_coordinates<const D: usize>(layers: &[Map]) -> HashMap<char, Vec<Vector<D>>> {
+    let mut coords = HashMap::<char, Vec<Vector<D>>>::new();
+    for (layer, map) in layers.iter().enumerate() {
+        for (row, row_values) in map.iter().enumerate() {
+            for (col, value) in row_values.iter().enumerate() {
+                if *value == '.' {
+                    continue;
+                }
+                coords
+                    .entry(*value)
+                    .or_default()
+                    .push(Vector::from_cell(layer, row, col));
             }
-            coords.entry(*value).or_default().push(Coordinate {
-                row: row.try_into().expect("Map too large"),
-                col: col.try_into().expect("Map too large"),
-            });
         }
     }
     return coords;
 }
 
-fn part1(map: &Map) {
-    let coords = map_to_coordinates(map);
-    let mut antinodes = AntinodeResult::new(map);
-    for (_, locations) in coords.iter() {
-        for (idx, loc1) in locations.iter().enumerate() {
-            for loc2 in locations.iter().skip(idx + 1) {
-                let delta = loc1 - loc2;
-                for node in vec![loc1 + delta, loc2 - delta] {
-                    antinodes.push(node);
-                }
+// The puzzle places antinodes twice the antenna distance away from the far
+// antenna, which generalises to ratio times that distance
+fn simple_antinodes<'a, const D: usize>(
+    area: &'a Area<D>,
+    locations: &[Vector<D>],
+    ratio: i32,
+) -> AntinodeResult<'a, D> {
+    let mut antinodes = AntinodeResult::new(area);
+    for (idx, loc1) in locations.iter().enumerate() {
+        for loc2 in locations.iter().skip(idx + 1) {
+            let delta = *loc1 - *loc2;
+            for node in [*loc2 + delta * ratio, *loc1 - delta * ratio] {
+                antinodes.push(node);
             }
         }
     }
-    println!("Total number of antinodes: {}", antinodes.nodes.len());
-}
-
-fn part2(map: &Map) {
-    let coords = map_to_coordinates(map);
-    let mut antinodes = AntinodeResult::new(map);
-    for (_, locations) in coords.iter() {
-        for (idx, loc1) in locations.iter().enumerate() {
-            for loc2 in locations.iter().skip(idx + 1) {
-                let delta = loc1 - loc2;
-                let mut mult = 0;
-                while antinodes.valid(&(loc1 + delta * mult))
-                    || antinodes.valid(&(loc1 - delta * mult))
-                {
-                    antinodes.push(loc1 + delta * mult);
-                    antinodes.push(loc1 - delta * mult);
-                    mult += 1;
-                }
+    return antinodes;
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        return a.abs();
+    }
+    return gcd(b, a % b);
+}
+
+// The puzzle steps by the full distance between antennas, the exact mode
+// divides it by the gcd so every grid point on the line is an antinode
+fn harmonic_antinodes<'a, const D: usize>(
+    area: &'a Area<D>,
+    locations: &[Vector<D>],
+    exact: bool,
+) -> AntinodeResult<'a, D> {
+    let mut antinodes = AntinodeResult::new(area);
+    for (idx, loc1) in locations.iter().enumerate() {
+        for loc2 in locations.iter().skip(idx + 1) {
+            let mut delta = *loc1 - *loc2;
+            if exact {
+                delta = delta.shrink(delta.0.iter().fold(0, |acc, x| gcd(acc, *x)));
+            }
+            let mut mult = 0;
+            while area.in_bounds(&(*loc1 + delta * mult)) || area.in_bounds(&(*loc1 - delta * mult))
+            {
+                antinodes.push(*loc1 + delta * mult);
+                antinodes.push(*loc1 - delta * mult);
+                mult += 1;
             }
         }
     }
-    println!(
-        "Total number of antinodes (with harmonics): {}",
-        antinodes.nodes.len()
-    );
+    return antinodes;
+}
+
+fn by_frequency<'a, const D: usize, F>(
+    layers: &[Map],
+    find: F,
+) -> BTreeMap<char, AntinodeResult<'a, D>>
+where
+    F: Fn(&Vec<Vector<D>>) -> AntinodeResult<'a, D>,
+{
+    return map_to_coordinates(layers)
+        .iter()
+        .map(|(frequency, locations)| (*frequency, find(locations)))
+        .collect();
+}
+
+fn sorted_nodes<const D: usize>(nodes: &HashSet<Vector<D>>) -> Vec<Vector<D>> {
+    let mut nodes: Vec<Vector<D>> = nodes.iter().copied().collect();
+    nodes.sort_by_key(|x| x.0);
+    return nodes;
+}
+
+// Antinodes are drawn over antennas, so every antinode shows as '#'
+fn render<const D: usize>(layers: &[Map], nodes: &HashSet<Vector<D>>) {
+    for (layer, map) in layers.iter().enumerate() {
+        if layer > 0 {
+            println!();
+        }
+        for (row, row_values) in map.iter().enumerate() {
+            let line: String = row_values
+                .iter()
+                .enumerate()
+                .map(|(col, value)| {
+                    if nodes.contains(&Vector::from_cell(layer, row, col)) {
+                        return '#';
+                    }
+                    return *value;
+                })
+                .collect();
+            println!("{}", line);
+        }
+    }
+}
+
+// Antennas may be any character but '.', including ones JSON needs escaped
+fn json_key(frequency: char) -> String {
+    return match frequency {
+        '"' => "\\\"".to_string(),
+        '\\' => "\\\\".to_string(),
+        c if c.is_control() => format!("\\u{:04x}", c as u32),
+        c => c.to_string(),
+    };
+}
+
+fn json_frequencies<const D: usize>(antinodes: &BTreeMap<char, AntinodeResult<D>>) -> String {
+    let frequencies: Vec<String> = antinodes
+        .iter()
+        .map(|(frequency, result)| {
+            let nodes: Vec<String> = sorted_nodes(&result.nodes)
+                .iter()
+                .map(|x| x.to_json())
+                .collect();
+            format!("\"{}\": [{}]", json_key(*frequency), nodes.join(", "))
+        })
+        .collect();
+    return format!("{{{}}}", frequencies.join(", "));
+}
+
+struct Options {
+    exact: bool,
+    ratio: i32,
+    by_frequency: bool,
+    draw: bool,
+    json: bool,
+}
+
+fn report<const D: usize>(
+    layers: &[Map],
+    label: &str,
+    antinodes: &BTreeMap<char, AntinodeResult<D>>,
+    options: &Options,
+) {
+    let all: HashSet<Vector<D>> = antinodes
+        .values()
+        .flat_map(|x| x.nodes.iter().copied())
+        .collect();
+    println!("Total number of antinodes{}: {}", label, all.len());
+    if options.by_frequency {
+        for (frequency, result) in antinodes.iter() {
+            println!("  {}: {}", frequency, result.nodes.len());
+        }
+    }
+    if options.draw {
+        render(layers, &all);
+    }
+}
+
+fn part1<'a, const D: usize>(
+    layers: &[Map],
+    area: &'a Area<D>,
+    options: &Options,
+) -> BTreeMap<char, AntinodeResult<'a, D>> {
+    let antinodes = by_frequency(layers, |x| simple_antinodes(area, x, options.ratio));
+    if !options.json {
+        let label = match options.ratio {
+            2 => String::new(),
+            ratio => format!(" (at ratio {})", ratio),
+        };
+        report(layers, &label, &antinodes, options);
+    }
+    return antinodes;
+}
+
+fn part2<'a, const D: usize>(
+    layers: &[Map],
+    area: &'a Area<D>,
+    options: &Options,
+) -> BTreeMap<char, AntinodeResult<'a, D>> {
+    let antinodes = by_frequency(layers, |x| harmonic_antinodes(area, x, options.exact));
+    if !options.json {
+        let label = match options.exact {
+            true => " (with exact harmonics)",
+            false => " (with harmonics)",
+        };
+        report(layers, label, &antinodes, options);
+    }
+    return antinodes;
+}
+
+fn run<const D: usize>(layers: &[Map], options: &Options) {
+    let area = Area::<D>::new(layers);
+    if !options.json {
+        let size: Vec<String> = (0..D)
+            .map(|idx| (area.high[idx] - area.low[idx] + 1).max(0).to_string())
+            .collect();
+        println!("Read map of size {}", size.join(", "));
+    }
+    let antinodes = part1(layers, &area, options);
+    let harmonics = part2(layers, &area, options);
+    if options.json {
+        println!(
+            "{{\"antinodes\": {}, \"harmonics\": {}}}",
+            json_frequencies(&antinodes),
+            json_frequencies(&harmonics)
+        );
+    }
 }
 
 fn main() {
-    let map = read_inputs();
-    println!("Read map of size {}, {}", map.len(), map[0].len());
-    part1(&map);
-    part2(&map);
+    let mut options = Options {
+        exact: false,
+        ratio: 2,
+        by_frequency: false,
+        draw: false,
+        json: false,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--exact" => options.exact = true,
+            "--ratio" => {
+                options.ratio = args
+                    .next()
+                    .expect("Missing ratio")
+                    .parse()
+                    .expect("Invalid ratio");
+                assert!(options.ratio >= 2, "Ratio must be at least 2");
+            }
+            "--by-frequency" => options.by_frequency = true,
+            "--draw" => options.draw = true,
+            "--json" => options.json = true,
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+    let layers = read_inputs();
+    match layers.len() {
+        1 => run::<2>(&layers, &options),
+        _ => run::<3>(&layers, &options),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The antennas of test-harmonics, a (2,4) step apart
+    fn harmonics() -> (Vec<Map>, Vec<Vector<2>>) {
+        let map: Map = [
+            "a.........",
+            "..........",
+            "....a.....",
+            "..........",
+            "..........",
+        ]
+        .iter()
+        .map(|x| x.chars().collect())
+        .collect();
+        return (vec![map], vec![Vector([0, 0]), Vector([2, 4])]);
+    }
+
+    fn cells(nodes: &HashSet<Vector<2>>) -> Vec<[i32; 2]> {
+        return sorted_nodes(nodes).iter().map(|x| x.0).collect();
+    }
+
+    #[test]
+    fn escapes_json_keys() {
+        assert_eq!(json_key('a'), "a");
+        assert_eq!(json_key('"'), "\\\"");
+        assert_eq!(json_key('\\'), "\\\\");
+        assert_eq!(json_key('\t'), "\\u0009");
+    }
+
+    #[test]
+    fn reduces_by_gcd() {
+        assert_eq!(gcd(2, 4), 2);
+        assert_eq!(gcd(-6, 4), 2);
+        assert_eq!(gcd(0, -3), 3);
+        assert_eq!(gcd(3, 5), 1);
+    }
+
+    #[test]
+    fn puzzle_mode_steps_by_full_distance() {
+        let (layers, antennas) = harmonics();
+        let area = Area::<2>::new(&layers);
+        let antinodes = harmonic_antinodes(&area, &antennas, false);
+        assert_eq!(cells(&antinodes.nodes), vec![[0, 0], [2, 4], [4, 8]]);
+    }
+
+    #[test]
+    fn exact_mode_finds_every_grid_point() {
+        let (layers, antennas) = harmonics();
+        let area = Area::<2>::new(&layers);
+        let antinodes = harmonic_antinodes(&area, &antennas, true);
+        assert_eq!(
+            cells(&antinodes.nodes),
+            vec![[0, 0], [1, 2], [2, 4], [3, 6], [4, 8]]
+        );
+    }
 }
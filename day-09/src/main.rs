@@ -1,4 +1,10 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::env;
+use std::fmt;
 use std::fs;
+use std::io::{self, BufRead, Read};
+use std::time::Instant;
 
 #[derive(Debug, Clone, Copy)]
 struct File {
@@ -12,54 +18,182 @@ enum Partition {
     Used(File),
 }
 
-fn part1() {
-    let mut content: Vec<u64> = fs::read_to_string("./input")
-        .expect("Missing input files")
-        .chars()
-        .filter_map(|c| c.to_string().parse().ok())
-        .collect();
-    println!("Content len: {}", content.len());
+// Digits packed two to a byte, half the size of the text they are read from
+struct DiskMap {
+    packed: Vec<u8>,
+    len: usize,
+}
+
+impl DiskMap {
+    fn new() -> Self {
+        return DiskMap {
+            packed: vec![],
+            len: 0,
+        };
+    }
+
+    fn push(&mut self, digit: u8) {
+        match self.len % 2 {
+            0 => self.packed.push(digit),
+            _ => *self.packed.last_mut().expect("No packed byte") |= digit << 4,
+        }
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        return self.len;
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    fn get(&self, idx: usize) -> u64 {
+        let byte = self.packed[idx / 2] >> (4 * (idx % 2));
+        return u64::from(byte & 0xf);
+    }
+
+    fn iter(&self) -> impl DoubleEndedIterator<Item = u64> + ExactSizeIterator + '_ {
+        return (0..self.len).map(|idx| self.get(idx));
+    }
+}
+
+impl FromIterator<u8> for DiskMap {
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        let mut disk_map = DiskMap::new();
+        for digit in iter {
+            disk_map.push(digit);
+        }
+        return disk_map;
+    }
+}
+
+#[derive(Debug)]
+enum ParseError {
+    Io(io::Error),
+    InvalidByte { offset: usize, byte: u8 },
+    DigitAfterWhitespace { offset: usize },
+    Empty,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::InvalidByte { offset, byte } => write!(
+                f,
+                "invalid character {:?} at byte offset {}",
+                char::from(*byte),
+                offset
+            ),
+            Self::DigitAfterWhitespace { offset } => {
+                write!(f, "digit after whitespace at byte offset {}", offset)
+            }
+            Self::Empty => write!(f, "no digits"),
+        };
+    }
+}
+
+// Reads the disk map a buffer at a time, whitespace is only allowed at the end
+fn parse_disk_map<R: Read>(reader: R) -> Result<DiskMap, ParseError> {
+    let mut reader = io::BufReader::new(reader);
+    let mut disk_map = DiskMap::new();
+    let mut offset = 0;
+    let mut trailing = false;
+    loop {
+        let buffer = reader.fill_buf().map_err(ParseError::Io)?;
+        if buffer.is_empty() {
+            break;
+        }
+        for byte in buffer.iter() {
+            match byte {
+                b'0'..=b'9' if trailing => {
+                    return Err(ParseError::DigitAfterWhitespace { offset });
+                }
+                b'0'..=b'9' => disk_map.push(byte - b'0'),
+                b' ' | b'\t' | b'\r' | b'\n' => trailing = true,
+                _ => {
+                    return Err(ParseError::InvalidByte {
+                        offset,
+                        byte: *byte,
+                    });
+                }
+            }
+            offset += 1;
+        }
+        let consumed = buffer.len();
+        reader.consume(consumed);
+    }
+    if disk_map.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    return Ok(disk_map);
+}
+
+fn read_inputs() -> DiskMap {
+    let file = fs::File::open("./input").expect("Missing input files");
+    return match parse_disk_map(file) {
+        Ok(disk_map) => disk_map,
+        Err(error) => panic!("Invalid disk map: {}", error),
+    };
+}
+
+// Takes blocks from the front and back of the disk map without copying it,
+// only the blocks left of the two digits being worked on are kept
+fn part1(disk_map: &DiskMap) {
+    println!("Content len: {}", disk_map.len());
     let mut total: u64 = 0;
     let mut start: usize = 0;
-    let mut end: usize = content.len() - 1;
+    // The map may end in free space, the last file is at the last even index
+    let mut end: usize = (disk_map.len() - 1) / 2 * 2;
+    let mut start_left = disk_map.get(start);
+    let mut end_left = disk_map.get(end);
     let mut idx: u64 = 0;
     loop {
-        // println!("{} {} {} {} {:?}", total, start, end, idx, content);
         if start % 2 == 0 {
             // file
-            if content[start] > 0 {
-                content[start] -= 1;
+            if start_left > 0 {
+                start_left -= 1;
                 total += (start / 2) as u64 * idx;
                 idx += 1;
             } else {
                 start += 1;
+                if start > end {
+                    break;
+                }
+                start_left = disk_map.get(start);
             }
         } else {
             // free
-            while content[end] == 0 && end > start {
+            while end_left == 0 && end > start {
                 end -= 2;
+                end_left = disk_map.get(end);
             }
             if end <= start {
                 break;
             }
-            if content[start] > 0 {
-                content[start] -= 1;
-                content[end] -= 1;
+            if start_left > 0 {
+                start_left -= 1;
+                end_left -= 1;
                 total += (end / 2) as u64 * idx;
                 idx += 1;
             } else {
                 start += 1;
+                // The file at the end may already have lost blocks
+                start_left = match start == end {
+                    true => end_left,
+                    false => disk_map.get(start),
+                };
             }
         }
     }
     println!("Calculate hash: {}", total);
 }
 
-fn part2() {
-    let mut content: Vec<Partition> = fs::read_to_string("./input")
-        .expect("Missing input files")
-        .chars()
-        .filter_map(|c| c.to_string().parse().ok())
+// Moves whole files by scanning the disk from the left for every file
+fn compact_files_scan(disk_map: &DiskMap) -> u64 {
+    let mut content: Vec<Partition> = disk_map
+        .iter()
         .enumerate()
         .map(|(idx, val)| {
             if idx % 2 == 0 {
@@ -109,10 +243,472 @@ fn part2() {
             }
         }
     }
+    return hash;
+}
+
+// Where a file ended up, from where it started
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    file: File,
+    from: u64,
+    to: u64,
+}
+
+// Gaps are kept in one min-heap of start positions per gap size, so the
+// leftmost gap a file fits in is on top of one of at most ten heaps. Space a
+// file leaves behind is never needed, as files only move left and the
+// remaining ones all start before it. Placements come in the order files are
+// moved, file start positions are worked out walking the disk map backwards.
+fn compact_files_with<F: FnMut(Placement)>(disk_map: &DiskMap, mut place: F) {
+    let mut gaps: Vec<BinaryHeap<Reverse<u64>>> = (0..10).map(|_| BinaryHeap::new()).collect();
+    let mut position: u64 = 0;
+    for (idx, size) in disk_map.iter().enumerate() {
+        if idx % 2 == 1 && size > 0 {
+            gaps[size as usize].push(Reverse(position));
+        }
+        position += size;
+    }
+    for (idx, size) in disk_map.iter().enumerate().rev() {
+        position -= size;
+        if idx % 2 == 1 {
+            continue;
+        }
+        let file = File {
+            index: idx as u64 / 2,
+            size,
+        };
+        let mut leftmost: Option<(u64, usize)> = None;
+        for gap_size in file.size as usize..gaps.len() {
+            let Some(Reverse(gap_start)) = gaps[gap_size].peek() else {
+                continue;
+            };
+            if *gap_start < position && leftmost.is_none_or(|(x, _)| *gap_start < x) {
+                leftmost = Some((*gap_start, gap_size));
+            }
+        }
+        let mut to = position;
+        if let Some((gap_start, gap_size)) = leftmost {
+            gaps[gap_size].pop();
+            let remaining = gap_size - file.size as usize;
+            if remaining > 0 {
+                gaps[remaining].push(Reverse(gap_start + file.size));
+            }
+            to = gap_start;
+        }
+        place(Placement {
+            file,
+            from: position,
+            to,
+        });
+    }
+}
+
+fn compact_files(disk_map: &DiskMap) -> Vec<Placement> {
+    let mut placements = vec![];
+    compact_files_with(disk_map, |x| placements.push(x));
+    return placements;
+}
+
+// Sum of the block positions the file covers, times its index
+fn placement_hash(placement: &Placement) -> u64 {
+    let size = placement.file.size;
+    return placement.file.index * (placement.to * size + size * size.saturating_sub(1) / 2);
+}
+
+fn placement_checksum(placements: &Vec<Placement>) -> u64 {
+    return placements.iter().map(placement_hash).sum();
+}
+
+// File index of every block, None for free space
+type Layout = Vec<Option<u64>>;
+
+const MAX_SHOWN_BLOCKS: usize = 200;
+
+fn expand(disk_map: &DiskMap) -> Layout {
+    let mut layout = vec![];
+    for (idx, size) in disk_map.iter().enumerate() {
+        let block = match idx % 2 {
+            0 => Some(idx as u64 / 2),
+            _ => None,
+        };
+        layout.extend((0..size).map(|_| block));
+    }
+    return layout;
+}
+
+fn layout_checksum(layout: &Layout) -> u64 {
+    return layout
+        .iter()
+        .enumerate()
+        .map(|(position, block)| position as u64 * block.unwrap_or(0))
+        .sum();
+}
+
+// Puzzle notation, files past index 9 only show their last digit
+fn render(layout: &Layout) -> String {
+    let mut line: String = layout
+        .iter()
+        .take(MAX_SHOWN_BLOCKS)
+        .map(|block| match block {
+            Some(index) => char::from_digit((index % 10) as u32, 10).expect("Not a digit"),
+            None => '.',
+        })
+        .collect();
+    if layout.len() > MAX_SHOWN_BLOCKS {
+        line += &format!(" ({} more blocks)", layout.len() - MAX_SHOWN_BLOCKS);
+    }
+    return line;
+}
+
+// Moves single blocks from the end into the leftmost free block
+fn compact_blocks(disk_map: &DiskMap, steps: bool) -> Layout {
+    let mut layout = expand(disk_map);
+    if layout.is_empty() {
+        return layout;
+    }
+    let mut start = 0;
+    let mut end = layout.len() - 1;
+    loop {
+        while start < end && layout[start].is_some() {
+            start += 1;
+        }
+        while start < end && layout[end].is_none() {
+            end -= 1;
+        }
+        if start >= end {
+            break;
+        }
+        layout.swap(start, end);
+        if steps {
+            println!("{}", render(&layout));
+        }
+    }
+    return layout;
+}
+
+fn apply_placements(disk_map: &DiskMap, placements: &Vec<Placement>, steps: bool) -> Layout {
+    let mut layout = expand(disk_map);
+    for placement in placements.iter() {
+        if placement.from == placement.to {
+            continue;
+        }
+        for offset in 0..placement.file.size {
+            layout.swap(
+                (placement.from + offset) as usize,
+                (placement.to + offset) as usize,
+            );
+        }
+        if steps {
+            println!("{}", render(&layout));
+        }
+    }
+    return layout;
+}
+
+// Which of the gaps left of a file it moves to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fit {
+    Leftmost,
+    Best,
+    Worst,
+}
+
+// Free spans indexed by start and by size, neighbouring spans are merged so
+// space freed by moved files can be reused as a whole
+struct Gaps {
+    by_start: BTreeMap<u64, u64>,
+    by_size: BTreeMap<u64, BTreeSet<u64>>,
+}
+
+impl Gaps {
+    fn new() -> Self {
+        return Gaps {
+            by_start: BTreeMap::new(),
+            by_size: BTreeMap::new(),
+        };
+    }
+
+    fn add(&mut self, start: u64, size: u64) {
+        if size == 0 {
+            return;
+        }
+        let (mut start, mut size) = (start, size);
+        if let Some((prev_start, prev_size)) = self.by_start.range(..start).next_back() {
+            if prev_start + prev_size == start {
+                start = *prev_start;
+                size += self.remove(start);
+            }
+        }
+        if self.by_start.contains_key(&(start + size)) {
+            size += self.remove(start + size);
+        }
+        self.by_start.insert(start, size);
+        self.by_size.entry(size).or_default().insert(start);
+    }
+
+    fn remove(&mut self, start: u64) -> u64 {
+        let size = self.by_start.remove(&start).expect("No such gap");
+        let starts = self.by_size.get_mut(&size).expect("Gap not indexed");
+        starts.remove(&start);
+        if starts.is_empty() {
+            self.by_size.remove(&size);
+        }
+        return size;
+    }
+
+    // Start of the gap a file of the given size moves to, only gaps starting
+    // before the limit count. Ties between equally sized gaps go left.
+    fn find(&self, size: u64, limit: u64, fit: Fit) -> Option<u64> {
+        let mut candidates = self.by_size.range(size..).filter_map(|(gap_size, starts)| {
+            starts
+                .first()
+                .filter(|x| **x < limit)
+                .map(|x| (*gap_size, *x))
+        });
+        let gap = match fit {
+            Fit::Leftmost => candidates.min_by_key(|(_, start)| *start),
+            Fit::Best => candidates.next(),
+            Fit::Worst => candidates.next_back(),
+        };
+        return gap.map(|(_, start)| start);
+    }
+}
+
+// Moves whole files from the highest index down, with more passes until
+// nothing moves if repeated. Only files that moved get a placement.
+fn move_files(disk_map: &DiskMap, fit: Fit, repeat: bool) -> Vec<Placement> {
+    let mut gaps = Gaps::new();
+    let mut files: Vec<(File, u64)> = vec![];
+    let mut position: u64 = 0;
+    for (idx, size) in disk_map.iter().enumerate() {
+        if idx % 2 == 0 {
+            let file = File {
+                index: idx as u64 / 2,
+                size,
+            };
+            files.push((file, position));
+        } else {
+            gaps.add(position, size);
+        }
+        position += size;
+    }
+    let mut placements = vec![];
+    loop {
+        let mut moved = false;
+        for (file, position) in files.iter_mut().rev() {
+            if file.size == 0 {
+                continue;
+            }
+            let Some(gap_start) = gaps.find(file.size, *position, fit) else {
+                continue;
+            };
+            let gap_size = gaps.remove(gap_start);
+            gaps.add(gap_start + file.size, gap_size - file.size);
+            gaps.add(*position, file.size);
+            placements.push(Placement {
+                file: *file,
+                from: *position,
+                to: gap_start,
+            });
+            *position = gap_start;
+            moved = true;
+        }
+        if !repeat || !moved {
+            break;
+        }
+    }
+    return placements;
+}
+
+struct Compaction {
+    layout: Layout,
+    checksum: u64,
+}
+
+trait CompactionStrategy {
+    fn name(&self) -> String;
+
+    // Final layout, printing the layout after every move if asked to
+    fn compact(&self, disk_map: &DiskMap, steps: bool) -> Layout;
+
+    fn run(&self, disk_map: &DiskMap, steps: bool) -> Compaction {
+        let layout = self.compact(disk_map, steps);
+        return Compaction {
+            checksum: layout_checksum(&layout),
+            layout,
+        };
+    }
+}
+
+struct BlockByBlock;
+
+impl CompactionStrategy for BlockByBlock {
+    fn name(&self) -> String {
+        return "block by block".to_string();
+    }
+
+    fn compact(&self, disk_map: &DiskMap, steps: bool) -> Layout {
+        return compact_blocks(disk_map, steps);
+    }
+}
+
+struct WholeFiles {
+    fit: Fit,
+    repeat: bool,
+}
+
+impl CompactionStrategy for WholeFiles {
+    fn name(&self) -> String {
+        let fit = match self.fit {
+            Fit::Leftmost => "leftmost",
+            Fit::Best => "best",
+            Fit::Worst => "worst",
+        };
+        let passes = match self.repeat {
+            true => "multiple passes",
+            false => "single pass",
+        };
+        return format!("whole files, {} fit, {}", fit, passes);
+    }
+
+    // The puzzle's own rule has a faster way to place files
+    fn compact(&self, disk_map: &DiskMap, steps: bool) -> Layout {
+        let placements = match (self.fit, self.repeat) {
+            (Fit::Leftmost, false) => compact_files(disk_map),
+            _ => move_files(disk_map, self.fit, self.repeat),
+        };
+        return apply_placements(disk_map, &placements, steps);
+    }
+}
+
+fn strategies() -> Vec<Box<dyn CompactionStrategy>> {
+    let mut strategies: Vec<Box<dyn CompactionStrategy>> = vec![Box::new(BlockByBlock)];
+    for repeat in [false, true] {
+        for fit in [Fit::Leftmost, Fit::Best, Fit::Worst] {
+            strategies.push(Box::new(WholeFiles { fit, repeat }));
+        }
+    }
+    return strategies;
+}
+
+// Prints the disk before and after every compaction, and every step in
+// between when the disk is small enough to follow
+fn show(disk_map: &DiskMap, steps: bool) {
+    let initial = expand(disk_map);
+    let steps = steps && initial.len() <= MAX_SHOWN_BLOCKS;
+    for strategy in strategies().iter() {
+        println!("{}:", strategy.name());
+        println!("{}", render(&initial));
+        let compaction = strategy.run(disk_map, steps);
+        if !steps {
+            println!("{}", render(&compaction.layout));
+        }
+        println!("Checksum: {}", compaction.checksum);
+    }
+}
+
+// Fragmentation is measured up to the last used block: how many free blocks
+// are left before it and in how many spans
+fn compare(digits: usize) {
+    let disk_map = generate_disk_map(digits);
+    println!("Generated disk map of {} digits", disk_map.len());
+    for strategy in strategies().iter() {
+        let start = Instant::now();
+        let compaction = strategy.run(&disk_map, false);
+        let elapsed = start.elapsed();
+        let end = compaction
+            .layout
+            .iter()
+            .rposition(|x| x.is_some())
+            .map_or(0, |x| x + 1);
+        let used = &compaction.layout[0..end];
+        let free = used.iter().filter(|x| x.is_none()).count();
+        let spans = used
+            .iter()
+            .enumerate()
+            .filter(|(idx, x)| x.is_none() && (*idx == 0 || used[idx - 1].is_some()))
+            .count();
+        println!(
+            "{}: checksum {}, data ends at block {}, {} free blocks in {} spans before it, {:?}",
+            strategy.name(),
+            compaction.checksum,
+            end,
+            free,
+            spans,
+            elapsed
+        );
+    }
+}
+
+// Small xorshift generator for benchmark disk maps
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    return *state;
+}
+
+// Files take 1 to 9 blocks, gaps 0 to 9, like the puzzle input
+fn generate_disk_map(digits: usize) -> DiskMap {
+    let mut state = 0x2024_0009;
+    return (0..digits)
+        .map(|idx| match idx % 2 {
+            0 => (next_random(&mut state) % 9 + 1) as u8,
+            _ => (next_random(&mut state) % 10) as u8,
+        })
+        .collect();
+}
+
+// The scan is quadratic and takes minutes on a million digits, so comparing
+// against it is optional
+fn bench(digits: usize, scan: bool) {
+    let disk_map = generate_disk_map(digits);
+    println!("Generated disk map of {} digits", disk_map.len());
+    let start = Instant::now();
+    let heaps = placement_checksum(&compact_files(&disk_map));
+    println!("Heaps: {} in {:?}", heaps, start.elapsed());
+    if !scan {
+        return;
+    }
+    let start = Instant::now();
+    let scan = compact_files_scan(&disk_map);
+    println!("Scan: {} in {:?}", scan, start.elapsed());
+    assert_eq!(heaps, scan);
+}
+
+fn part2(disk_map: &DiskMap) {
+    let mut hash: u64 = 0;
+    compact_files_with(disk_map, |x| hash += placement_hash(&x));
     println!("Calculated hash with partition: {}", hash);
 }
 
 fn main() {
-    part1();
-    part2();
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|x| x.as_str()) == Some("bench") {
+        let digits = args
+            .get(2)
+            .filter(|x| *x != "--scan")
+            .map(|x| x.parse().expect("Invalid digit count"))
+            .unwrap_or(1_000_000);
+        bench(digits, args.iter().any(|x| x == "--scan"));
+        return;
+    }
+    if args.get(1).map(|x| x.as_str()) == Some("compare") {
+        let digits = args
+            .get(2)
+            .map(|x| x.parse().expect("Invalid digit count"))
+            .unwrap_or(20_000);
+        compare(digits);
+        return;
+    }
+    let disk_map = read_inputs();
+    if args.get(1).map(|x| x.as_str()) == Some("--show") {
+        show(
+            &disk_map,
+            args.get(2).map(|x| x.as_str()) == Some("--steps"),
+        );
+        return;
+    }
+    part1(&disk_map);
+    part2(&disk_map);
 }
@@ -1,29 +1,57 @@
 use std::collections::VecDeque;
+use std::env;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::ops::RangeInclusive;
+use std::time::Instant;
 
 #[derive(Debug, PartialEq, Eq)]
 struct Map<I>(Vec<Vec<I>>);
 
+// None for cells no trail can cross
+type Height = Option<u8>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Order {
+    RowMajor,
+    ColumnMajor,
+}
+
 impl<I> Map<I> {
-    fn iter(&self) -> MapIter<I> {
-        MapIter {
+    fn iter(&self) -> MapIter<'_, I> {
+        return self.iter_in(Order::RowMajor);
+    }
+
+    fn iter_in(&self, order: Order) -> MapIter<'_, I> {
+        let mut iter = MapIter {
             map: self,
-            row: 0,
-            col: 0,
-        }
+            order,
+            front: (0, 0),
+            back: (0, 0),
+            remaining: self.0.iter().map(|x| x.len()).sum(),
+        };
+        iter.back = (iter.majors(), 0);
+        return iter;
     }
 
-    fn adjacents(&self, pos: &MapPosition<I>) -> Vec<MapPosition<I>> {
+    fn width(&self) -> usize {
+        return self.0.iter().map(|x| x.len()).max().unwrap_or(0);
+    }
+
+    fn adjacents(&self, pos: &MapPosition<I>, diagonals: bool) -> Vec<MapPosition<'_, I>> {
+        let mut deltas = vec![(-1, 0), (1, 0), (0, -1), (0, 1)];
+        if diagonals {
+            deltas.extend([(-1, -1), (-1, 1), (1, -1), (1, 1)]);
+        }
         let mut adjacents: Vec<MapPosition<I>> = vec![];
-        for (delta_row, delta_col) in vec![(-1, 0), (1, 0), (0, -1), (0, 1)] {
+        for (delta_row, delta_col) in deltas {
             let Some(new_row) = pos.row.checked_add_signed(delta_row) else {
                 continue;
             };
             let Some(new_col) = pos.col.checked_add_signed(delta_col) else {
                 continue;
             };
-            if new_row < self.0.len() && new_col < self.0[0].len() {
+            if new_row < self.0.len() && new_col < self.0[new_row].len() {
                 adjacents.push(MapPosition {
                     value: &self.0[new_row][new_col],
                     row: new_row,
@@ -42,89 +70,719 @@ struct MapPosition<'a, I> {
     col: usize,
 }
 
+// Walks the cells of a map, rows may have any length including none. Cursors
+// are (major, minor) indices, so (row, col) for row-major order and
+// (col, row) for column-major. The back cursor points just past its cell.
 struct MapIter<'a, I> {
     map: &'a Map<I>,
-    row: usize,
-    col: usize,
+    order: Order,
+    front: (usize, usize),
+    back: (usize, usize),
+    remaining: usize,
+}
+
+impl<'a, I> MapIter<'a, I> {
+    fn majors(&self) -> usize {
+        return match self.order {
+            Order::RowMajor => self.map.0.len(),
+            Order::ColumnMajor => self.map.width(),
+        };
+    }
+
+    fn minors(&self, major: usize) -> usize {
+        return match self.order {
+            Order::RowMajor => self.map.0[major].len(),
+            Order::ColumnMajor => self.map.0.len(),
+        };
+    }
+
+    fn cell(&self, major: usize, minor: usize) -> Option<MapPosition<'a, I>> {
+        let (row, col) = match self.order {
+            Order::RowMajor => (major, minor),
+            Order::ColumnMajor => (minor, major),
+        };
+        let map = self.map;
+        return map.0[row]
+            .get(col)
+            .map(|value| MapPosition { value, row, col });
+    }
 }
 
 impl<'a, I> Iterator for MapIter<'a, I> {
     type Item = MapPosition<'a, I>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.col += 1;
-        if self.col >= self.map.0[self.row].len() {
-            self.row += 1;
-            self.col = 0;
+        if self.remaining == 0 {
+            return None;
         }
-        if self.row >= self.map.0.len() {
+        loop {
+            let (major, minor) = self.front;
+            if minor >= self.minors(major) {
+                self.front = (major + 1, 0);
+                continue;
+            }
+            self.front = (major, minor + 1);
+            if let Some(position) = self.cell(major, minor) {
+                self.remaining -= 1;
+                return Some(position);
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.remaining, Some(self.remaining));
+    }
+}
+
+impl<'a, I> DoubleEndedIterator for MapIter<'a, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
             return None;
         }
-        return Some(MapPosition {
-            value: &self.map.0[self.row][self.col],
-            row: self.row,
-            col: self.col,
-        });
+        loop {
+            let (major, minor) = self.back;
+            if minor == 0 {
+                self.back = (major - 1, self.minors(major - 1));
+                continue;
+            }
+            self.back = (major, minor - 1);
+            if let Some(position) = self.cell(major, minor - 1) {
+                self.remaining -= 1;
+                return Some(position);
+            }
+        }
+    }
+}
+
+impl<'a, I> ExactSizeIterator for MapIter<'a, I> {}
+
+// What counts as a trail: the heights it starts and ends at, how much the
+// height may change in one step and whether steps may go diagonally
+struct TrailRules {
+    steps: RangeInclusive<i8>,
+    diagonals: bool,
+    start: u8,
+    end: u8,
+}
+
+impl TrailRules {
+    fn puzzle() -> Self {
+        return TrailRules {
+            steps: 1..=1,
+            diagonals: false,
+            start: 0,
+            end: 9,
+        };
+    }
+
+    fn allows(&self, from: &Height, to: &Height) -> bool {
+        let (Some(from), Some(to)) = (from, to) else {
+            return false;
+        };
+        return self.steps.contains(&(*to as i8 - *from as i8));
+    }
+
+    // Trails must not be able to come back to a height, so steps either all
+    // go up or all go down
+    fn ascending(&self) -> bool {
+        return *self.steps.start() > 0;
     }
 }
 
-fn read_inputs() -> Map<u8> {
+fn read_inputs() -> Map<Height> {
     let file = File::open("./input").expect("Input file missing");
     return Map(io::BufReader::new(file)
         .lines()
         .flatten()
         .map(|line| {
             line.chars()
-                .map(|x| x.to_string().parse().expect("Not a number"))
+                .map(|x| match x {
+                    '.' => None,
+                    _ => Some(x.to_digit(10).expect("Not a height") as u8),
+                })
                 .collect()
         })
         .collect());
 }
 
-fn part1(map: &Map<u8>) {
+fn height_char(value: &Height) -> char {
+    return match value {
+        Some(height) => char::from_digit(*height as u32, 10).unwrap_or('?'),
+        None => '.',
+    };
+}
+
+fn score_bfs(map: &Map<Height>, rules: &TrailRules) -> usize {
     let mut total = 0;
-    for start in map.iter().filter(|x| *x.value == 0) {
+    for start in map.iter().filter(|x| *x.value == Some(rules.start)) {
         let mut queue: VecDeque<_> = [start].into();
-        let mut reached: Vec<MapPosition<u8>> = vec![];
+        let mut reached: Vec<MapPosition<Height>> = vec![];
         while let Some(position) = queue.pop_front() {
-            if *position.value == 9 && !reached.contains(&position) {
-                reached.push(position);
+            if *position.value == Some(rules.end) {
+                if !reached.contains(&position) {
+                    reached.push(position);
+                }
+                continue;
             }
             for adjacent in map
-                .adjacents(&position)
+                .adjacents(&position, rules.diagonals)
                 .iter()
-                .filter(|x| *x.value == *position.value + 1)
+                .filter(|x| rules.allows(position.value, x.value))
             {
                 queue.push_back(*adjacent);
             }
         }
         total += reached.len();
     }
-    println!("Total of ratings (part 1): {}", total);
+    return total;
 }
 
-fn part2(map: &Map<u8>) {
+fn rating_bfs(map: &Map<Height>, rules: &TrailRules) -> usize {
     let mut total = 0;
-    for start in map.iter().filter(|x| *x.value == 0) {
+    for start in map.iter().filter(|x| *x.value == Some(rules.start)) {
         let mut queue: VecDeque<_> = [start].into();
         while let Some(position) = queue.pop_front() {
-            if *position.value == 9 {
+            if *position.value == Some(rules.end) {
                 total += 1;
+                continue;
             }
             for adjacent in map
-                .adjacents(&position)
+                .adjacents(&position, rules.diagonals)
                 .iter()
-                .filter(|x| *x.value == *position.value + 1)
+                .filter(|x| rules.allows(position.value, x.value))
             {
                 queue.push_back(*adjacent);
             }
         }
     }
+    return total;
+}
+
+// Bitset over cell numbers kept as sorted (word, bits) pairs. Summits reached
+// from a cell are at most nine rows away, so only a few words are ever set.
+#[derive(Debug, Clone, Default)]
+struct SparseBits(Vec<(usize, u64)>);
+
+impl SparseBits {
+    fn single(bit: usize) -> Self {
+        return SparseBits(vec![(bit / 64, 1 << (bit % 64))]);
+    }
+
+    fn union(&self, other: &SparseBits) -> SparseBits {
+        let mut words = Vec::with_capacity(self.0.len() + other.0.len());
+        let (mut left, mut right) = (0, 0);
+        while left < self.0.len() && right < other.0.len() {
+            let (a, b) = (self.0[left], other.0[right]);
+            if a.0 == b.0 {
+                words.push((a.0, a.1 | b.1));
+                left += 1;
+                right += 1;
+            } else if a.0 < b.0 {
+                words.push(a);
+                left += 1;
+            } else {
+                words.push(b);
+                right += 1;
+            }
+        }
+        words.extend_from_slice(&self.0[left..]);
+        words.extend_from_slice(&other.0[right..]);
+        return SparseBits(words);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        return self.0.iter().flat_map(|(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| word * 64 + bit)
+        });
+    }
+
+    fn count(&self) -> usize {
+        return self.0.iter().map(|x| x.1.count_ones() as usize).sum();
+    }
+}
+
+// Summits reachable from a cell and the number of distinct trails to them
+#[derive(Debug, Clone, Default)]
+struct Trails {
+    summits: SparseBits,
+    paths: usize,
+}
+
+// Visits cells from the end height back towards the start, so every cell only
+// combines what the neighbours it may step to already know
+fn trail_counts(map: &Map<Height>, rules: &TrailRules) -> Vec<Vec<Trails>> {
+    let mut trails: Vec<Vec<Trails>> = map
+        .0
+        .iter()
+        .map(|row| vec![Trails::default(); row.len()])
+        .collect();
+    let mut by_height: Vec<Vec<MapPosition<Height>>> = vec![vec![]; 10];
+    for position in map.iter() {
+        if let Some(height) = position.value {
+            by_height[*height as usize].push(position);
+        }
+    }
+    if !rules.ascending() {
+        by_height.reverse();
+    }
+    let cols = map.width();
+    for cells in by_height.iter().rev() {
+        for position in cells.iter() {
+            if *position.value == Some(rules.end) {
+                trails[position.row][position.col] = Trails {
+                    summits: SparseBits::single(position.row * cols + position.col),
+                    paths: 1,
+                };
+                continue;
+            }
+            let mut current = Trails::default();
+            for adjacent in map
+                .adjacents(position, rules.diagonals)
+                .iter()
+                .filter(|x| rules.allows(position.value, x.value))
+            {
+                let next = &trails[adjacent.row][adjacent.col];
+                current.summits = current.summits.union(&next.summits);
+                current.paths += next.paths;
+            }
+            trails[position.row][position.col] = current;
+        }
+    }
+    return trails;
+}
+
+fn trailheads<'a>(
+    map: &Map<Height>,
+    trails: &'a Vec<Vec<Trails>>,
+    rules: &TrailRules,
+) -> Vec<&'a Trails> {
+    return map
+        .iter()
+        .filter(|x| *x.value == Some(rules.start))
+        .map(|x| &trails[x.row][x.col])
+        .collect();
+}
+
+// Summits a trailhead reaches, its score and rating
+struct Trailhead {
+    row: usize,
+    col: usize,
+    summits: Vec<(usize, usize)>,
+    rating: usize,
+}
+
+fn describe_trailheads(
+    map: &Map<Height>,
+    trails: &Vec<Vec<Trails>>,
+    rules: &TrailRules,
+) -> Vec<Trailhead> {
+    let cols = map.width();
+    return map
+        .iter()
+        .filter(|x| *x.value == Some(rules.start))
+        .map(|x| {
+            let found = &trails[x.row][x.col];
+            Trailhead {
+                row: x.row,
+                col: x.col,
+                summits: found.summits.iter().map(|x| (x / cols, x % cols)).collect(),
+                rating: found.paths,
+            }
+        })
+        .collect();
+}
+
+// Every distinct trail from a cell up to a summit. Only cells that still have
+// a trail ahead of them are entered, so no branch is walked in vain.
+fn hiking_trails(
+    map: &Map<Height>,
+    trails: &Vec<Vec<Trails>>,
+    rules: &TrailRules,
+    start: (usize, usize),
+) -> Vec<Vec<(usize, usize)>> {
+    let mut found = vec![];
+    let mut path = vec![start];
+    walk_trails(map, trails, rules, &mut path, &mut found);
+    return found;
+}
+
+fn walk_trails(
+    map: &Map<Height>,
+    trails: &Vec<Vec<Trails>>,
+    rules: &TrailRules,
+    path: &mut Vec<(usize, usize)>,
+    found: &mut Vec<Vec<(usize, usize)>>,
+) {
+    let (row, col) = *path.last().expect("Empty trail");
+    let position = MapPosition {
+        value: &map.0[row][col],
+        row,
+        col,
+    };
+    if *position.value == Some(rules.end) {
+        found.push(path.clone());
+        return;
+    }
+    for adjacent in map
+        .adjacents(&position, rules.diagonals)
+        .iter()
+        .filter(|x| rules.allows(position.value, x.value) && trails[x.row][x.col].paths > 0)
+    {
+        path.push((adjacent.row, adjacent.col));
+        walk_trails(map, trails, rules, path, found);
+        path.pop();
+    }
+}
+
+// Draws a trail with its heights on an otherwise empty map, as in the puzzle
+fn render_trail(map: &Map<Height>, trail: &Vec<(usize, usize)>) {
+    for (row, row_values) in map.0.iter().enumerate() {
+        let line: String = row_values
+            .iter()
+            .enumerate()
+            .map(|(col, value)| match trail.contains(&(row, col)) {
+                true => height_char(value),
+                false => '.',
+            })
+            .collect();
+        println!("{}", line);
+    }
+}
+
+fn json_trailheads(trailheads: &Vec<Trailhead>) -> String {
+    let entries: Vec<String> = trailheads
+        .iter()
+        .map(|x| {
+            let summits: Vec<String> = x
+                .summits
+                .iter()
+                .map(|(row, col)| format!("[{}, {}]", row, col))
+                .collect();
+            format!(
+                "{{\"row\": {}, \"col\": {}, \"score\": {}, \"rating\": {}, \"summits\": [{}]}}",
+                x.row,
+                x.col,
+                x.summits.len(),
+                x.rating,
+                summits.join(", ")
+            )
+        })
+        .collect();
+    return format!("[{}]", entries.join(", "));
+}
+
+// Without a trailhead lists all of them, with one lists its trails and draws
+// the chosen one
+fn show_trails(
+    map: &Map<Height>,
+    trails: &Vec<Vec<Trails>>,
+    rules: &TrailRules,
+    start: Option<(usize, usize)>,
+    choice: usize,
+) {
+    let Some(start) = start else {
+        for trailhead in describe_trailheads(map, trails, rules).iter() {
+            let summits: Vec<String> = trailhead
+                .summits
+                .iter()
+                .map(|(row, col)| format!("{},{}", row, col))
+                .collect();
+            println!(
+                "Trailhead {},{}: score {}, rating {}, summits {}",
+                trailhead.row,
+                trailhead.col,
+                trailhead.summits.len(),
+                trailhead.rating,
+                summits.join(" ")
+            );
+        }
+        return;
+    };
+    let value = map.0.get(start.0).and_then(|x| x.get(start.1));
+    assert!(
+        value == Some(&Some(rules.start)),
+        "No trailhead at {},{}",
+        start.0,
+        start.1
+    );
+    let found = hiking_trails(map, trails, rules, start);
+    if found.is_empty() {
+        println!("No trails from {},{}", start.0, start.1);
+        return;
+    }
+    for (idx, trail) in found.iter().enumerate() {
+        let cells: Vec<String> = trail
+            .iter()
+            .map(|(row, col)| format!("{},{}", row, col))
+            .collect();
+        println!("Trail {}: {}", idx + 1, cells.join(" -> "));
+    }
+    let trail = choice
+        .checked_sub(1)
+        .and_then(|x| found.get(x))
+        .expect("No such trail");
+    println!("Trail {}:", choice);
+    render_trail(map, trail);
+}
+
+// Small xorshift generator for benchmark maps
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    return *state;
+}
+
+// Diagonal slopes give every trailhead hundreds of trails, a few random
+// cells break some of them up
+fn generate_map(size: usize) -> Map<Height> {
+    let mut state = 0x2024_0010;
+    let mut map = vec![];
+    for row in 0..size {
+        let mut row_values = vec![];
+        for col in 0..size {
+            let height = match next_random(&mut state) % 8 {
+                0 => next_random(&mut state) % 10,
+                _ => ((row + col) % 10) as u64,
+            };
+            row_values.push(Some(height as u8));
+        }
+        map.push(row_values);
+    }
+    return Map(map);
+}
+
+fn bench(size: usize, rules: &TrailRules) {
+    let map = generate_map(size);
+    println!("Generated map of size {}, {}", size, size);
+    let start = Instant::now();
+    let trails = trail_counts(&map, rules);
+    let heads = trailheads(&map, &trails, rules);
+    let score: usize = heads.iter().map(|x| x.summits.count()).sum();
+    let rating: usize = heads.iter().map(|x| x.paths).sum();
+    println!("DP: {} / {} in {:?}", score, rating, start.elapsed());
+    let start = Instant::now();
+    let bfs_score = score_bfs(&map, rules);
+    let bfs_rating = rating_bfs(&map, rules);
+    println!(
+        "BFS: {} / {} in {:?}",
+        bfs_score,
+        bfs_rating,
+        start.elapsed()
+    );
+    assert_eq!((score, rating), (bfs_score, bfs_rating));
+}
+
+// Lists the cells in the order the map iterator visits them
+fn cells(map: &Map<Height>, order: Order, reverse: bool) {
+    let iter = map.iter_in(order);
+    println!("{} cells", iter.len());
+    let positions: Vec<MapPosition<Height>> = match reverse {
+        true => iter.rev().collect(),
+        false => iter.collect(),
+    };
+    for position in positions.iter() {
+        println!(
+            "{},{}: {}",
+            position.row,
+            position.col,
+            height_char(position.value)
+        );
+    }
+}
+
+fn part1(map: &Map<Height>, trails: &Vec<Vec<Trails>>, rules: &TrailRules) {
+    let total: usize = trailheads(map, trails, rules)
+        .iter()
+        .map(|x| x.summits.count())
+        .sum();
+    println!("Total of ratings (part 1): {}", total);
+}
+
+fn part2(map: &Map<Height>, trails: &Vec<Vec<Trails>>, rules: &TrailRules) {
+    let total: usize = trailheads(map, trails, rules).iter().map(|x| x.paths).sum();
     println!("Total of ratings (part 2): {}", total);
 }
 
+fn parse_height(value: Option<String>) -> u8 {
+    let height = value
+        .expect("Missing height")
+        .parse()
+        .expect("Invalid height");
+    assert!(height <= 9, "Heights go from 0 to 9");
+    return height;
+}
+
 fn main() {
+    let mut rules = TrailRules::puzzle();
+    let mut args: Vec<String> = vec![];
+    let mut all_args = env::args().skip(1);
+    while let Some(arg) = all_args.next() {
+        match arg.as_str() {
+            "--steps" => {
+                let steps = all_args.next().expect("Missing steps");
+                let (low, high) = steps.split_once("..").unwrap_or((&steps, &steps));
+                let low: i8 = low.parse().expect("Invalid step");
+                let high: i8 = high.parse().expect("Invalid step");
+                assert!(
+                    low <= high && (low > 0 || high < 0),
+                    "Steps must all go up or all go down"
+                );
+                rules.steps = low..=high;
+            }
+            "--diagonals" => rules.diagonals = true,
+            "--start" => rules.start = parse_height(all_args.next()),
+            "--end" => rules.end = parse_height(all_args.next()),
+            _ => args.push(arg),
+        }
+    }
+    if args.first().map(|x| x.as_str()) == Some("bench") {
+        let size = args
+            .get(1)
+            .map(|x| x.parse().expect("Invalid map size"))
+            .unwrap_or(300);
+        bench(size, &rules);
+        return;
+    }
     let map = read_inputs();
-    part1(&map);
-    part2(&map);
+    if args.first().map(|x| x.as_str()) == Some("cells") {
+        let order = match args.get(1).map(|x| x.as_str()) {
+            Some("columns") => Order::ColumnMajor,
+            _ => Order::RowMajor,
+        };
+        cells(&map, order, args.iter().any(|x| x == "--reverse"));
+        return;
+    }
+    let trails = trail_counts(&map, &rules);
+    if args.first().map(|x| x.as_str()) == Some("trails") {
+        let start = args.get(1).map(|x| {
+            let (row, col) = x.split_once(",").expect("Trailhead must be row,col");
+            (
+                row.parse().expect("Invalid row"),
+                col.parse().expect("Invalid column"),
+            )
+        });
+        let choice = args
+            .get(2)
+            .map(|x| x.parse().expect("Invalid trail number"))
+            .unwrap_or(1);
+        show_trails(&map, &trails, &rules, start, choice);
+        return;
+    }
+    if args.first().map(|x| x.as_str()) == Some("--json") {
+        println!(
+            "{}",
+            json_trailheads(&describe_trailheads(&map, &trails, &rules))
+        );
+        return;
+    }
+    part1(&map, &trails, &rules);
+    part2(&map, &trails, &rules);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ragged() -> Map<u8> {
+        return Map(vec![vec![0, 1], vec![], vec![2, 3, 4]]);
+    }
+
+    fn cells_of<'a>(iter: impl Iterator<Item = MapPosition<'a, u8>>) -> Vec<(usize, usize)> {
+        return iter.map(|x| (x.row, x.col)).collect();
+    }
+
+    fn heights(rows: &[&str]) -> Map<Height> {
+        return Map(rows
+            .iter()
+            .map(|row| {
+                row.chars()
+                    .map(|x| x.to_digit(10).map(|x| x as u8))
+                    .collect()
+            })
+            .collect());
+    }
+
+    #[test]
+    fn first_item_is_origin() {
+        let map = ragged();
+        let first = map.iter().next().expect("Empty iterator");
+        assert_eq!((first.row, first.col, *first.value), (0, 0, 0));
+        let first = map
+            .iter_in(Order::ColumnMajor)
+            .next()
+            .expect("Empty iterator");
+        assert_eq!((first.row, first.col), (0, 0));
+    }
+
+    #[test]
+    fn skips_empty_rows() {
+        let map = ragged();
+        assert_eq!(
+            cells_of(map.iter()),
+            vec![(0, 0), (0, 1), (2, 0), (2, 1), (2, 2)]
+        );
+        assert_eq!(
+            cells_of(map.iter_in(Order::ColumnMajor)),
+            vec![(0, 0), (2, 0), (0, 1), (2, 1), (2, 2)]
+        );
+    }
+
+    #[test]
+    fn len_counts_ragged_rows() {
+        let map = ragged();
+        assert_eq!(map.iter().len(), 5);
+        assert_eq!(map.iter_in(Order::ColumnMajor).len(), 5);
+        assert_eq!(Map::<u8>(vec![vec![], vec![]]).iter().len(), 0);
+        let mut iter = map.iter();
+        iter.next();
+        iter.next_back();
+        assert_eq!(iter.len(), 3);
+    }
+
+    #[test]
+    fn front_and_back_meet() {
+        let map = ragged();
+        for order in [Order::RowMajor, Order::ColumnMajor] {
+            let mut iter = map.iter_in(order);
+            let mut seen = vec![];
+            loop {
+                let Some(front) = iter.next() else {
+                    break;
+                };
+                seen.push((front.row, front.col));
+                let Some(back) = iter.next_back() else {
+                    break;
+                };
+                seen.push((back.row, back.col));
+            }
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next_back(), None);
+            seen.sort();
+            assert_eq!(seen, vec![(0, 0), (0, 1), (2, 0), (2, 1), (2, 2)]);
+        }
+    }
+
+    #[test]
+    fn reverses_in_both_orders() {
+        let map = ragged();
+        for order in [Order::RowMajor, Order::ColumnMajor] {
+            let mut forward = cells_of(map.iter_in(order));
+            forward.reverse();
+            assert_eq!(cells_of(map.iter_in(order).rev()), forward);
+        }
+    }
+
+    #[test]
+    fn counts_trailhead_at_origin() {
+        let map = heights(&["0123", "1234", "8765", "9876"]);
+        let rules = TrailRules::puzzle();
+        let trails = trail_counts(&map, &rules);
+        let heads = trailheads(&map, &trails, &rules);
+        assert_eq!(heads.len(), 1);
+        assert_eq!(heads[0].summits.count(), 1);
+        assert_eq!(heads[0].paths, 16);
+    }
 }